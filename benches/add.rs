@@ -0,0 +1,54 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use merkle_tree::merkle_tree::MerkleTree;
+
+const ADDS: u64 = 16;
+
+// Adding to a built tree only rehashes one path, while editing the leaves rebuilds every level.
+// Every iteration adds a few leaves, reading the root after each one, so reallocating the leaves
+// doesn't dominate
+fn add(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add");
+    for size in [1 << 8, 1 << 11, 1 << 14] {
+        let transactions: Vec<u64> = (0..size).collect();
+
+        group.bench_with_input(
+            BenchmarkId::new("incremental", size),
+            &transactions,
+            |b, transactions| {
+                b.iter_batched_ref(
+                    || MerkleTree::new(transactions.clone()).unwrap(),
+                    |merkle_tree| {
+                        for transaction in size..size + ADDS {
+                            merkle_tree.add(black_box(transaction)).unwrap();
+                            black_box(merkle_tree.root());
+                        }
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("rebuild", size),
+            &transactions,
+            |b, transactions| {
+                b.iter_batched_ref(
+                    || MerkleTree::new(transactions.clone()).unwrap(),
+                    |merkle_tree| {
+                        for transaction in size..size + ADDS {
+                            merkle_tree.leaves_mut().push(black_box(transaction));
+                            black_box(merkle_tree.root());
+                        }
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, add);
+criterion_main!(benches);
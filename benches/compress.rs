@@ -0,0 +1,59 @@
+use std::cell::RefCell;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use merkle_tree::hash::Hash;
+use merkle_tree::hasher::{MerkleHasher, Sha256MerkleHasher};
+use sha2::{Digest, Sha256};
+
+// Hashing a level of a binary tree into its parents, one pair at a time through the hasher or with
+// every block given straight to the compression function, one at a time but without buffering
+fn hash_level(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hash_level");
+    for size in [1 << 8, 1 << 14] {
+        let nodes: Vec<Hash> = (0..size).map(Hash::from).collect();
+
+        group.bench_with_input(BenchmarkId::new("scalar", size), &nodes, |b, nodes| {
+            b.iter(|| {
+                black_box(nodes)
+                    .chunks_exact(2)
+                    .map(|pair| Sha256MerkleHasher::hash_pair(pair[0], pair[1]))
+                    .collect::<Vec<_>>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("unbuffered", size), &nodes, |b, nodes| {
+            b.iter(|| Sha256MerkleHasher::hash_each_pair(black_box(nodes)))
+        });
+    }
+    group.finish();
+}
+
+// A fresh SHA-256 for every pair against one kept in a thread local and reset as it finishes
+fn sha256_setup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sha256_setup");
+    group.bench_function("new", |b| {
+        b.iter(|| {
+            let mut hasher = Sha256::new();
+            hasher.update(black_box(1u64).to_be_bytes());
+            hasher.update(black_box(2u64).to_be_bytes());
+            hasher.finalize()
+        })
+    });
+
+    thread_local! {
+        static HASHER: RefCell<Sha256> = RefCell::new(Sha256::new());
+    }
+    group.bench_function("thread_local", |b| {
+        b.iter(|| {
+            HASHER.with_borrow_mut(|hasher| {
+                hasher.update(black_box(1u64).to_be_bytes());
+                hasher.update(black_box(2u64).to_be_bytes());
+                hasher.finalize_reset()
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, hash_level, sha256_setup);
+criterion_main!(benches);
@@ -0,0 +1,98 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use merkle_tree::config::MerkleConfig;
+use merkle_tree::merkle_tree::{LeafIndex, MerkleTree};
+
+fn verify(c: &mut Criterion) {
+    let mut group = c.benchmark_group("verify");
+    for height in [4, 10, 16] {
+        let transactions: Vec<u64> = (0..1 << height).collect();
+        let merkle_tree = MerkleTree::new(transactions).unwrap();
+        let tree_head = merkle_tree.tree_head_at(0);
+        let proof = merkle_tree.proof_for(LeafIndex(1)).unwrap();
+
+        group.bench_with_input(BenchmarkId::from_parameter(height), &proof, |b, proof| {
+            b.iter(|| MerkleTree::verify_with_tree_head(black_box(&1), proof, &tree_head))
+        });
+    }
+    group.finish();
+}
+
+// Proofs only index one contiguous level after another, so this should grow with the height alone
+fn proof_for(c: &mut Criterion) {
+    let mut group = c.benchmark_group("proof_for");
+    for height in [4, 10, 16] {
+        let transactions: Vec<u64> = (0..1 << height).collect();
+        let merkle_tree = MerkleTree::new(transactions).unwrap();
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(height),
+            &merkle_tree,
+            |b, merkle_tree| b.iter(|| merkle_tree.proof_for(black_box(LeafIndex(1)))),
+        );
+    }
+    group.finish();
+}
+
+// With the index, finding the leaf of a value shouldn't grow with the number of leaves, while
+// searching goes through every leaf hash for the last one
+fn proof_by_value(c: &mut Criterion) {
+    let mut group = c.benchmark_group("proof_by_value");
+    for height in [4, 10, 16] {
+        let transactions: Vec<u64> = (0..1 << height).collect();
+        let last = (1 << height) - 1;
+        let config = MerkleConfig::default().index_leaves(true);
+        let indexed = MerkleTree::with_config(transactions.clone(), config).unwrap();
+        let searched = MerkleTree::new(transactions).unwrap();
+
+        for (name, merkle_tree) in [("indexed", &indexed), ("searched", &searched)] {
+            let mut proof = Vec::new();
+            group.bench_with_input(BenchmarkId::new(name, height), merkle_tree, |b, tree| {
+                b.iter(|| tree.get_proof_into(black_box(&last), &mut proof))
+            });
+        }
+    }
+    group.finish();
+}
+
+// DefaultHasher can't be reset, so the only way to reuse one is cloning a fresh prototype
+fn hasher_setup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hasher_setup");
+    group.bench_function("new", |b| {
+        b.iter(|| {
+            let mut hasher = DefaultHasher::new();
+            black_box(1u64).hash(&mut hasher);
+            black_box(2u64).hash(&mut hasher);
+            hasher.finish()
+        })
+    });
+
+    let prototype = DefaultHasher::new();
+    group.bench_function("clone", |b| {
+        b.iter(|| {
+            let mut hasher = prototype.clone();
+            black_box(1u64).hash(&mut hasher);
+            black_box(2u64).hash(&mut hasher);
+            hasher.finish()
+        })
+    });
+
+    // A thread local hasher still has to be cloned, as SipHash can't be reset
+    thread_local! {
+        static PROTOTYPE: DefaultHasher = DefaultHasher::new();
+    }
+    group.bench_function("thread_local", |b| {
+        b.iter(|| {
+            let mut hasher = PROTOTYPE.with(DefaultHasher::clone);
+            black_box(1u64).hash(&mut hasher);
+            black_box(2u64).hash(&mut hasher);
+            hasher.finish()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, verify, proof_for, proof_by_value, hasher_setup);
+criterion_main!(benches);
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use crate::hash::Hash;
+use crate::hasher::MerkleHasher;
+use crate::leaf::LeafEncode;
+use crate::merkle_tree::{LeafIndex, MerkleTree, SiblingHash};
+
+/// Proofs of several leaves, from one or more trees, with every distinct sibling stored once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofBatch {
+    pub siblings: Vec<SiblingHash>,
+    /// For every requested leaf, in order, the positions of its siblings in `siblings`.
+    pub proofs: Vec<Vec<usize>>,
+}
+
+impl ProofBatch {
+    pub fn proof(&self, request: usize) -> Option<Vec<SiblingHash>> {
+        let positions = self.proofs.get(request)?;
+        Some(
+            positions
+                .iter()
+                .map(|position| self.siblings[*position])
+                .collect(),
+        )
+    }
+}
+
+/// Proves several leaves of one tree at once, labeled with their index and leaf hash, for quorum
+/// checks that at least some number of them are included, see
+/// [`crate::merkle_tree::MerkleTree::verify_quorum`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuorumProof {
+    pub leaves: Vec<(LeafIndex, Hash)>,
+    /// The proof of every leaf, in the order of `leaves`.
+    pub batch: ProofBatch,
+}
+
+/// Builds [`ProofBatch`]es over trees that share most of their structure, like consecutive
+/// versions of the same dataset, where proofs of the same leaves mostly have the same siblings.
+///
+/// Siblings are shared when they are the same hash on the same side. Proofs that don't overlap
+/// save nothing, and pay for the positions on top of their siblings.
+pub struct ProofBatcher<'a, H: LeafEncode, M: MerkleHasher> {
+    trees: Vec<&'a MerkleTree<H, M>>,
+}
+
+impl<'a, H: LeafEncode, M: MerkleHasher> ProofBatcher<'a, H, M> {
+    pub fn new(trees: Vec<&'a MerkleTree<H, M>>) -> Self {
+        Self { trees }
+    }
+
+    /// Proves every `(tree, index)` pair, where `tree` is a position in the trees given to
+    /// [`ProofBatcher::new`]. Returns `None` if any tree or leaf doesn't exist.
+    pub fn batch(&self, requests: &[(usize, LeafIndex)]) -> Option<ProofBatch> {
+        let mut batch = ProofBatch {
+            siblings: Vec::new(),
+            proofs: Vec::with_capacity(requests.len()),
+        };
+        let mut positions: HashMap<SiblingHash, usize> = HashMap::new();
+        for (tree, index) in requests {
+            let proof = self.trees.get(*tree)?.proof_for(*index)?;
+            let proof = proof
+                .into_iter()
+                .map(|sibling_hash| {
+                    *positions.entry(sibling_hash).or_insert_with(|| {
+                        batch.siblings.push(sibling_hash);
+                        batch.siblings.len() - 1
+                    })
+                })
+                .collect();
+            batch.proofs.push(proof);
+        }
+        Some(batch)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use crate::batch::ProofBatcher;
+    use crate::merkle_tree::{LeafIndex, MerkleTree};
+
+    #[test]
+    fn a_proof_batch_stores_the_siblings_shared_between_trees_once() {
+        let old_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E", "F", "G", "H"]).unwrap();
+        let new_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E", "F", "G", "I"]).unwrap();
+        let batcher = ProofBatcher::new(vec![&old_tree, &new_tree]);
+        let requests = [(0, LeafIndex(0)), (1, LeafIndex(0))];
+        let batch = batcher.batch(&requests).unwrap();
+
+        // Only the sibling on the side of the changed leaf differs, the two below it are shared
+        assert_eq!(batch.siblings.len(), 4);
+        assert_eq!(batch.proofs[0][..2], batch.proofs[1][..2]);
+        assert_ne!(batch.proofs[0][2], batch.proofs[1][2]);
+        for (request, (tree, index)) in requests.iter().enumerate() {
+            let tree = [&old_tree, &new_tree][*tree];
+            assert_eq!(batch.proof(request), tree.proof_for(*index));
+        }
+
+        assert!(batcher.batch(&[(2, LeafIndex(0))]).is_none());
+        assert!(batcher.batch(&[(0, LeafIndex(8))]).is_none());
+    }
+}
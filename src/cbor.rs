@@ -0,0 +1,180 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::error::MerkleError;
+use crate::hash::Hash;
+use crate::merkle_tree::{LeafIndex, SiblingHash, Witness};
+use crate::proof::Proof;
+
+// Siblings go as (hash, is_left) pairs, like the ones MerkleTree::verify_hex takes
+#[derive(Serialize, Deserialize)]
+struct CborProof {
+    algorithm: u8,
+    version: u64,
+    salt: Option<u64>,
+    leaf_index: usize,
+    siblings: Vec<(u64, bool)>,
+}
+
+impl CborProof {
+    fn new(proof: &Proof, leaf_index: LeafIndex) -> Self {
+        Self {
+            algorithm: proof.algorithm,
+            version: proof.version,
+            salt: proof.salt,
+            leaf_index: leaf_index.0,
+            siblings: to_pairs(&proof.siblings),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CborWitness<H> {
+    leaf: H,
+    index: usize,
+    siblings: Vec<(u64, bool)>,
+}
+
+fn to_pairs(siblings: &[SiblingHash]) -> Vec<(u64, bool)> {
+    siblings
+        .iter()
+        .map(|sibling_hash| match sibling_hash {
+            SiblingHash::Left(hash) => (u64::from(*hash), true),
+            SiblingHash::Right(hash) => (u64::from(*hash), false),
+        })
+        .collect()
+}
+
+fn from_pairs(pairs: Vec<(u64, bool)>) -> Vec<SiblingHash> {
+    pairs
+        .into_iter()
+        .map(|(hash, is_left)| {
+            if is_left {
+                SiblingHash::Left(Hash::from(hash))
+            } else {
+                SiblingHash::Right(Hash::from(hash))
+            }
+        })
+        .collect()
+}
+
+/// Encodes the proof of the leaf at `leaf_index` as CBOR, a self describing binary format that
+/// is much smaller than JSON for verifiers on constrained links.
+pub fn proof_to_cbor(proof: &Proof, leaf_index: LeafIndex) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&CborProof::new(proof, leaf_index), &mut bytes)
+        .expect("a proof is always encodable as CBOR");
+    bytes
+}
+
+pub fn proof_from_cbor(bytes: &[u8]) -> Result<(LeafIndex, Proof), MerkleError> {
+    let cbor_proof: CborProof =
+        ciborium::from_reader(bytes).map_err(|_| MerkleError::MalformedProof)?;
+    let siblings = from_pairs(cbor_proof.siblings);
+
+    Ok((
+        LeafIndex(cbor_proof.leaf_index),
+        Proof {
+            algorithm: cbor_proof.algorithm,
+            version: cbor_proof.version,
+            salt: cbor_proof.salt,
+            siblings,
+        },
+    ))
+}
+
+/// Encodes a [`Witness`] as CBOR, with its leaf in the leaf's own serde representation. Fails
+/// with [`MerkleError::Encode`] if the leaf can't be serialized.
+pub fn witness_to_cbor<H: Serialize>(witness: &Witness<H>) -> Result<Vec<u8>, MerkleError> {
+    let cbor_witness = CborWitness {
+        leaf: &witness.leaf,
+        index: witness.index.0,
+        siblings: to_pairs(&witness.proof),
+    };
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&cbor_witness, &mut bytes)
+        .map_err(|error| MerkleError::Encode(error.to_string()))?;
+    Ok(bytes)
+}
+
+pub fn witness_from_cbor<H: DeserializeOwned>(bytes: &[u8]) -> Result<Witness<H>, MerkleError> {
+    let cbor_witness: CborWitness<H> =
+        ciborium::from_reader(bytes).map_err(|_| MerkleError::MalformedProof)?;
+    Ok(Witness {
+        leaf: cbor_witness.leaf,
+        index: LeafIndex(cbor_witness.index),
+        proof: from_pairs(cbor_witness.siblings),
+    })
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use crate::cbor::{
+        proof_from_cbor, proof_to_cbor, witness_from_cbor, witness_to_cbor, CborProof,
+    };
+    use crate::error::MerkleError;
+    use crate::merkle_tree::{LeafIndex, MerkleTree, Witness};
+    use serde::{ser, Serialize, Serializer};
+
+    struct Unencodable;
+
+    impl Serialize for Unencodable {
+        fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+            Err(ser::Error::custom("no encoding"))
+        }
+    }
+
+    #[test]
+    fn a_proof_can_be_encoded_as_cbor_smaller_than_json() {
+        let transactions: Vec<u32> = (0..1000).collect();
+        let merkle_tree = MerkleTree::new(transactions).unwrap();
+        let proof = merkle_tree.detached_proof(LeafIndex(637)).unwrap();
+        let bytes = proof_to_cbor(&proof, LeafIndex(637));
+
+        let (leaf_index, decoded) = proof_from_cbor(&bytes).unwrap();
+        assert_eq!(leaf_index, LeafIndex(637));
+        assert_eq!(decoded, proof);
+        assert_eq!(
+            MerkleTree::verify_detached(&637, &decoded, merkle_tree.root()),
+            Ok(true)
+        );
+
+        let json = serde_json::to_vec(&CborProof::new(&proof, LeafIndex(637))).unwrap();
+        assert!(bytes.len() < json.len());
+        assert_eq!(
+            proof_from_cbor(&bytes[..bytes.len() - 1]),
+            Err(MerkleError::MalformedProof)
+        );
+    }
+
+    #[test]
+    fn a_witness_can_be_encoded_as_cbor_and_verified() {
+        let transactions: Vec<String> = ["A", "B", "C"].map(String::from).to_vec();
+        let merkle_tree = MerkleTree::new(transactions).unwrap();
+        let witness = merkle_tree.witness(LeafIndex(2)).unwrap();
+        let bytes = witness_to_cbor(&witness).unwrap();
+
+        let decoded: Witness<String> = witness_from_cbor(&bytes).unwrap();
+        assert_eq!(decoded, witness);
+        assert!(merkle_tree.verify_witness(&decoded));
+        assert_eq!(
+            witness_from_cbor::<String>(&bytes[..bytes.len() - 1]),
+            Err(MerkleError::MalformedProof)
+        );
+    }
+
+    #[test]
+    fn a_witness_whose_leaf_fails_to_serialize_is_an_error() {
+        let witness = Witness {
+            leaf: Unencodable,
+            index: LeafIndex(0),
+            proof: Vec::new(),
+        };
+
+        assert!(matches!(
+            witness_to_cbor(&witness),
+            Err(MerkleError::Encode(reason)) if reason.contains("no encoding")
+        ));
+    }
+}
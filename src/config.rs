@@ -0,0 +1,211 @@
+use std::iter;
+
+use crate::error::MerkleError;
+use crate::hash::Hash;
+use crate::hasher::MerkleHasher;
+use crate::merkle_tree::SiblingHash;
+
+/// How a node group smaller than the arity, at the end of a level, gets its parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OddStrategy {
+    /// The last node is repeated to fill the group.
+    #[default]
+    Duplicate,
+    /// The group is filled with [`Hash::default`].
+    ZeroPad,
+    /// Only the nodes present are hashed, so a lone node goes up unchanged.
+    ///
+    /// A lone node at the end of several levels in a row, like the last leaf of a tree of
+    /// `2^k + 1`, would make a chain of parents with a single son each. Their hash is their son's,
+    /// and they add no sibling to its proof, so the chain is already collapsed into a direct edge
+    /// when the tree is built: proofs follow the true branching, and there is nothing to compact.
+    Promote,
+}
+
+/// Construction options for [`crate::merkle_tree::MerkleTree::with_config`], built from the
+/// default binary tree that duplicates lone nodes.
+///
+/// The parent of a group of nodes is their left fold with the binary pair hash, so proofs of any
+/// arity are still made of left and right siblings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MerkleConfig {
+    pub(crate) arity: usize,
+    pub(crate) odd_strategy: OddStrategy,
+    pub(crate) salt: Option<u64>,
+    pub(crate) allow_empty: bool,
+    pub(crate) index_leaves: bool,
+    pub(crate) max_leaves: Option<usize>,
+    pub(crate) iterations: u32,
+    pub(crate) reject_zero_siblings: bool,
+}
+
+impl Default for MerkleConfig {
+    fn default() -> Self {
+        Self {
+            arity: 2,
+            odd_strategy: OddStrategy::Duplicate,
+            salt: None,
+            allow_empty: false,
+            index_leaves: false,
+            max_leaves: None,
+            iterations: 1,
+            reject_zero_siblings: false,
+        }
+    }
+}
+
+impl MerkleConfig {
+    /// Number of sons of every node, at least 2.
+    pub fn arity(mut self, arity: usize) -> Self {
+        self.arity = arity;
+        self
+    }
+
+    pub fn odd_strategy(mut self, odd_strategy: OddStrategy) -> Self {
+        self.odd_strategy = odd_strategy;
+        self
+    }
+
+    /// See [`crate::merkle_tree::MerkleTree::new_salted`].
+    pub fn salt(mut self, salt: u64) -> Self {
+        self.salt = Some(salt);
+        self
+    }
+
+    /// Lets the tree have no leaves, with [`crate::merkle_tree::MerkleTree::EMPTY_ROOT`] as its
+    /// root, instead of failing with [`crate::error::MerkleError::EmptyTransactions`].
+    pub fn allow_empty(mut self, allow_empty: bool) -> Self {
+        self.allow_empty = allow_empty;
+        self
+    }
+
+    /// Keeps a map from leaf hash to index, so proofs by value look their leaf up instead of
+    /// searching through every leaf hash, at the cost of memory for the map.
+    pub fn index_leaves(mut self, index_leaves: bool) -> Self {
+        self.index_leaves = index_leaves;
+        self
+    }
+
+    /// Rejects building or growing the tree past `max_leaves` with
+    /// [`MerkleError::TooManyLeaves`], so untrusted callers can't make it use unbounded memory.
+    /// Leaves edited through [`crate::merkle_tree::MerkleTree::leaves_mut`] aren't checked.
+    pub fn max_leaves(mut self, max_leaves: usize) -> Self {
+        self.max_leaves = Some(max_leaves);
+        self
+    }
+
+    /// Hashes every leaf `iterations` times, each time over the hash before, so leaves that are
+    /// low-entropy secrets are that much slower to brute force from their leaf hash. The default
+    /// of 1, like 0, hashes them once. Verifying with the tree, detached proofs included through
+    /// [`crate::merkle_tree::MerkleTree::verify_versioned`], hashes them the same way, but the
+    /// functions that don't take a tree, like
+    /// [`crate::merkle_tree::MerkleTree::verify_detached`], hash them once.
+    pub fn iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Makes verifying reject a proof with a sibling equal to [`Hash::default`], unless lone nodes
+    /// are padded with it. [`crate::merkle_tree::MerkleTree::verify_at`] and
+    /// [`crate::merkle_tree::MerkleTree::verify_versioned`] fail with
+    /// [`MerkleError::SuspiciousZeroSibling`], while every other verifier bound to the tree, like
+    /// `verify`, `verify_compact` or a prepared verifier, returns false. Such a sibling more likely
+    /// comes from uninitialized or zeroed out bytes than from a node that hashed to zero.
+    ///
+    /// This is a heuristic to catch bugs, not a security guarantee: a forged proof doesn't need
+    /// zero siblings, and it is still rejected by its root alone.
+    pub fn reject_zero_siblings(mut self, reject_zero_siblings: bool) -> Self {
+        self.reject_zero_siblings = reject_zero_siblings;
+        self
+    }
+
+    // Zero siblings are legitimate padding when lone nodes are zero padded
+    pub(crate) fn is_suspicious_sibling(&self, sibling_hash: &SiblingHash) -> bool {
+        let (SiblingHash::Left(hash) | SiblingHash::Right(hash)) = sibling_hash;
+        self.reject_zero_siblings
+            && self.odd_strategy != OddStrategy::ZeroPad
+            && *hash == Hash::default()
+    }
+
+    pub(crate) fn check_zero_siblings(&self, proof: &[SiblingHash]) -> Result<(), MerkleError> {
+        match proof
+            .iter()
+            .position(|sibling| self.is_suspicious_sibling(sibling))
+        {
+            Some(level) => Err(MerkleError::SuspiciousZeroSibling { level }),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_leaf_count(&self, leaves: usize) -> Result<(), MerkleError> {
+        match self.max_leaves {
+            Some(limit) if leaves > limit => Err(MerkleError::TooManyLeaves { limit, got: leaves }),
+            _ => Ok(()),
+        }
+    }
+
+    // The group as it is hashed, up to `arity` nodes filled in by the odd strategy
+    pub(crate) fn padded_group<'a>(&self, group: &'a [Hash]) -> impl Iterator<Item = Hash> + 'a {
+        let (padding, missing) = match self.odd_strategy {
+            OddStrategy::Duplicate => (group[group.len() - 1], self.arity - group.len()),
+            OddStrategy::ZeroPad => (Hash::default(), self.arity - group.len()),
+            OddStrategy::Promote => (Hash::default(), 0),
+        };
+        group
+            .iter()
+            .copied()
+            .chain(iter::repeat_n(padding, missing))
+    }
+
+    // The builders only pass non-empty groups, an empty one means their chunking is broken
+    pub(crate) fn create_parent<M: MerkleHasher>(
+        &self,
+        group: &[Hash],
+    ) -> Result<Hash, MerkleError> {
+        let Some(&first_son) = group.first() else {
+            return Err(MerkleError::Internal("a parent can't be made from no sons"));
+        };
+        Ok(self
+            .padded_group(group)
+            .skip(1)
+            .fold(first_son, M::hash_pair))
+    }
+
+    // The number of siblings in the proof of the leaf at `index`, the way the proof is written:
+    // one for all the sons on the left, folded together, and one per son on the right, counting
+    // the padding unless lone nodes are promoted
+    pub(crate) fn proof_length(&self, mut leaves: usize, mut index: usize) -> usize {
+        let mut length = 0;
+        while leaves > 1 {
+            let position = index % self.arity;
+            let sons = match self.odd_strategy {
+                OddStrategy::Promote => (leaves - (index - position)).min(self.arity),
+                _ => self.arity,
+            };
+            length += usize::from(position > 0) + sons - position - 1;
+            index /= self.arity;
+            leaves = leaves.div_ceil(self.arity);
+        }
+        length
+    }
+
+    // How many nodes the odd strategy adds to fill the last group of every level, none when lone
+    // nodes are promoted
+    pub(crate) fn padded_nodes(&self, mut leaves: usize) -> usize {
+        if self.odd_strategy == OddStrategy::Promote {
+            return 0;
+        }
+        let mut padded = 0;
+        while leaves > 1 {
+            padded += (self.arity - leaves % self.arity) % self.arity;
+            leaves = leaves.div_ceil(self.arity);
+        }
+        padded
+    }
+
+    // The position of a leaf can be read from the sides of its siblings only when every level
+    // adds exactly one of them
+    pub(crate) fn has_positional_proofs(&self) -> bool {
+        self.arity == 2 && self.odd_strategy != OddStrategy::Promote
+    }
+}
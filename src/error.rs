@@ -0,0 +1,174 @@
+use std::fmt;
+use std::io;
+
+use crate::hash::Hash;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MerkleError {
+    /// A tree needs at least one leaf, unless it is built to allow being empty.
+    EmptyTransactions,
+    IndexOutOfBounds(usize),
+    /// A tree needs at least two sons per node.
+    BadArity(usize),
+    /// The proof was produced with a different hashing algorithm than the verifier uses.
+    AlgorithmMismatch {
+        expected: u8,
+        found: u8,
+    },
+    MalformedProof,
+    /// The proof doesn't have as many siblings as the leaf it claims to prove is deep.
+    WrongDepth {
+        expected: usize,
+        got: usize,
+    },
+    /// A sibling of the proof is the zero hash, in a tree that doesn't pad with it, see
+    /// [`crate::config::MerkleConfig::reject_zero_siblings`].
+    SuspiciousZeroSibling {
+        level: usize,
+    },
+    /// Fewer siblings arrived than the serialized proof announces.
+    TruncatedProof {
+        expected: usize,
+        found: usize,
+    },
+    /// The proof was issued at another version of the tree, which has been changed since.
+    StaleProof {
+        issued: u64,
+        current: u64,
+    },
+    BadHex(String),
+    /// A root given as a string isn't a number in the radix it was said to be in.
+    BadRoot {
+        root: String,
+        radix: u32,
+    },
+    /// A flat array of nodes doesn't have the length of a tree with the given number of leaves.
+    BadArrayLength {
+        expected: usize,
+        found: usize,
+    },
+    /// The order given to [`crate::merkle_tree::MerkleTree::new_with_order`] doesn't list every
+    /// position of the leaves exactly once.
+    BadOrder,
+    /// The tree would have more leaves than [`crate::config::MerkleConfig::max_leaves`] allows.
+    TooManyLeaves {
+        limit: usize,
+        got: usize,
+    },
+    /// A leaf value is missing: the tree has none to work on, as it was built from leaf hashes or
+    /// pruned, or a leaf streamed into a tree that keeps values has none to keep.
+    NoLeafValues,
+    /// Reading a streamed leaf failed, see [`crate::merkle_tree::MerkleTree::add_reader`].
+    Read(io::ErrorKind),
+    /// A value couldn't be encoded, with the reason the encoder gave, like a leaf whose own
+    /// `Serialize` failed.
+    Encode(String),
+    /// An invariant of the tree construction was broken, which is a bug in this crate.
+    Internal(&'static str),
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleError::EmptyTransactions => write!(
+                f,
+                "Can't create a tree without transactions, at least one leaf is required"
+            ),
+            MerkleError::IndexOutOfBounds(index) => write!(f, "Index {index} is out of bounds"),
+            MerkleError::BadArity(arity) => {
+                write!(
+                    f,
+                    "A tree can't have {arity} sons per node, it needs at least 2"
+                )
+            }
+            MerkleError::AlgorithmMismatch { expected, found } => write!(
+                f,
+                "Proof was built with algorithm {found}, but the verifier uses algorithm {expected}"
+            ),
+            MerkleError::MalformedProof => write!(f, "Proof bytes are malformed"),
+            MerkleError::WrongDepth { expected, got } => write!(
+                f,
+                "Proof has {got} siblings, but the leaf it claims is {expected} levels deep"
+            ),
+            MerkleError::SuspiciousZeroSibling { level } => {
+                write!(f, "Proof has a zero hash sibling at level {level}")
+            }
+            MerkleError::TruncatedProof { expected, found } => write!(
+                f,
+                "Proof is truncated, it has {found} of its {expected} siblings"
+            ),
+            MerkleError::StaleProof { issued, current } => write!(
+                f,
+                "Proof was issued at version {issued}, but the tree is at version {current}"
+            ),
+            MerkleError::BadHex(hex) => write!(f, "{hex:?} is not a valid hex hash"),
+            MerkleError::BadRoot { root, radix } => {
+                write!(f, "{root:?} is not a valid root in radix {radix}")
+            }
+            MerkleError::BadArrayLength { expected, found } => {
+                write!(f, "Expected an array of {expected} nodes, found {found}")
+            }
+            MerkleError::BadOrder => {
+                write!(f, "The order must list every leaf position exactly once")
+            }
+            MerkleError::TooManyLeaves { limit, got } => {
+                write!(f, "A tree can't have {got} leaves, the limit is {limit}")
+            }
+            MerkleError::NoLeafValues => write!(f, "A leaf value is missing"),
+            MerkleError::Read(kind) => write!(f, "Couldn't read the leaf: {kind}"),
+            MerkleError::Encode(reason) => write!(f, "Couldn't encode the value: {reason}"),
+            MerkleError::Internal(reason) => write!(f, "Internal error: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for MerkleError {}
+
+/// A node of a built tree that doesn't agree with the nodes below it, found by
+/// [`crate::merkle_tree::MerkleTree::validate_structure`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StructuralError {
+    /// The tree couldn't be built to be checked.
+    Build(MerkleError),
+    /// A level doesn't have one node per group of sons on the level below, so some node has no
+    /// son at all, or the top level isn't a single root.
+    LevelLength {
+        level: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// The hash stored for a node isn't the one computed from its sons.
+    NodeMismatch {
+        level: usize,
+        index: usize,
+        stored: Hash,
+        computed: Hash,
+    },
+}
+
+impl fmt::Display for StructuralError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StructuralError::Build(error) => write!(f, "The tree couldn't be built: {error}"),
+            StructuralError::LevelLength {
+                level,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Level {level} should have {expected} nodes, but it has {found}"
+            ),
+            StructuralError::NodeMismatch {
+                level,
+                index,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "Node {index} of level {level} stores {stored}, but its sons hash to {computed}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StructuralError {}
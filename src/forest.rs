@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use crate::error::MerkleError;
+use crate::hash::Hash;
+use crate::leaf::LeafEncode;
+use crate::merkle_tree::{MerkleTree, SiblingHash};
+
+/// Independent trees kept by their root, so a proof can be checked against whichever of them it
+/// claims to belong to.
+///
+/// Trees are only lent out immutably, as changing one would leave it under a stale root.
+pub struct MerkleForest<H: LeafEncode> {
+    trees: HashMap<Hash, MerkleTree<H>>,
+}
+
+impl<H: LeafEncode> Default for MerkleForest<H> {
+    fn default() -> Self {
+        Self {
+            trees: HashMap::new(),
+        }
+    }
+}
+
+impl<H: LeafEncode> MerkleForest<H> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the tree under its root, which is returned, replacing any tree with the same root.
+    pub fn insert(&mut self, merkle_tree: MerkleTree<H>) -> Result<Hash, MerkleError> {
+        let root = merkle_tree.try_root()?;
+        self.trees.insert(root, merkle_tree);
+        Ok(root)
+    }
+
+    pub fn get(&self, root: Hash) -> Option<&MerkleTree<H>> {
+        self.trees.get(&root)
+    }
+
+    pub fn len(&self) -> usize {
+        self.trees.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trees.is_empty()
+    }
+
+    /// Verifies the proof against the tree with `claimed_root`, failing if there is none.
+    pub fn verify_in_forest(
+        &self,
+        transaction: H,
+        proof: &[SiblingHash],
+        claimed_root: Hash,
+    ) -> bool {
+        self.get(claimed_root)
+            .is_some_and(|merkle_tree| merkle_tree.verify_iter(transaction, proof.iter().copied()))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use crate::forest::MerkleForest;
+    use crate::hash::Hash;
+    use crate::merkle_tree::{LeafIndex, MerkleTree};
+
+    #[test]
+    fn a_proof_only_verifies_against_its_own_tree_in_a_forest() {
+        let trees = [
+            vec!["A", "B"],
+            vec!["A", "C", "D"],
+            vec!["B", "D", "E", "F"],
+        ];
+        let mut forest = MerkleForest::new();
+        let roots: Vec<Hash> = trees
+            .iter()
+            .map(|transactions| {
+                let merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+                forest.insert(merkle_tree).unwrap()
+            })
+            .collect();
+        assert_eq!(forest.len(), 3);
+
+        for (tree, transactions) in trees.iter().enumerate() {
+            let proof = forest
+                .get(roots[tree])
+                .unwrap()
+                .proof_for(LeafIndex(1))
+                .unwrap();
+            for (other_tree, root) in roots.iter().enumerate() {
+                assert_eq!(
+                    forest.verify_in_forest(transactions[1], &proof, *root),
+                    tree == other_tree
+                );
+            }
+        }
+
+        let proof = forest
+            .get(roots[0])
+            .unwrap()
+            .proof_for(LeafIndex(0))
+            .unwrap();
+        assert!(!forest.verify_in_forest("A", &proof, Hash::from(0)));
+    }
+}
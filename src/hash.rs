@@ -0,0 +1,69 @@
+use std::fmt;
+
+/// A hash computed by the tree, for a leaf or for a node.
+///
+/// It only comes out of hashing or an explicit conversion, so a plain number can't be passed where
+/// a hash is expected by accident.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Hash(u64);
+
+impl Hash {
+    pub const fn new(hash: u64) -> Self {
+        Self(hash)
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 8] {
+        self.0.to_be_bytes()
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 8]) -> Self {
+        Self(u64::from_be_bytes(bytes))
+    }
+}
+
+impl From<u64> for Hash {
+    fn from(hash: u64) -> Self {
+        Self(hash)
+    }
+}
+
+impl From<Hash> for u64 {
+    fn from(hash: Hash) -> Self {
+        hash.0
+    }
+}
+
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl fmt::LowerHex for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+impl fmt::UpperHex for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::UpperHex::fmt(&self.0, f)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use crate::hash::Hash;
+
+    #[test]
+    fn a_hash_can_be_converted_to_and_from_u64() {
+        let hash = Hash::from(0x0102030405060708);
+
+        assert_eq!(u64::from(hash), 0x0102030405060708);
+        assert_eq!(hash.to_be_bytes(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(Hash::from_be_bytes(hash.to_be_bytes()), hash);
+        assert_eq!(hash.to_string(), "0102030405060708");
+        assert_eq!(format!("{hash:x}"), "102030405060708");
+    }
+}
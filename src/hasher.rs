@@ -0,0 +1,219 @@
+use std::hash::{DefaultHasher, Hasher};
+#[cfg(feature = "sha2-direct-compress")]
+use std::slice;
+
+use crate::hash::Hash;
+
+/// The hash function of a tree, for its leaves and for its nodes, see
+/// [`crate::merkle_tree::MerkleTree::with_hasher`].
+///
+/// Bytes are written in pieces and the hash is finished once, so leaves can be streamed through
+/// it. Hashes are 64 bits whatever the backend, longer digests are cut to their first 8 bytes,
+/// read as big endian.
+pub trait MerkleHasher: Default {
+    /// Tag carried by every [`crate::proof::Proof`] made with this hasher.
+    const ALGORITHM: u8;
+    /// The hash of no bytes at all, which is the root of a tree without leaves.
+    const EMPTY_ROOT: Hash;
+
+    fn write(&mut self, bytes: &[u8]);
+
+    fn finish(self) -> Hash;
+
+    /// A hasher ready to be given the bytes of a leaf, which backends can override to write a
+    /// prefix first, so leaves and nodes never hash alike.
+    fn leaf_hasher() -> Self {
+        Self::default()
+    }
+
+    /// The hash of a node, with its sons written as big endian bytes.
+    fn hash_pair(left: Hash, right: Hash) -> Hash {
+        let mut hasher = Self::default();
+        hasher.write(&left.to_be_bytes());
+        hasher.write(&right.to_be_bytes());
+        hasher.finish()
+    }
+
+    /// Hashes the nodes two by two, one pair after another, the way a level of a binary tree is
+    /// hashed into its parents, a lone last node being left to the caller. Backends can override
+    /// how each pair is hashed, as long as every parent is the same as [`MerkleHasher::hash_pair`]
+    /// gives. Nothing is hashed in parallel or with SIMD.
+    fn hash_each_pair(nodes: &[Hash]) -> Vec<Hash> {
+        nodes
+            .chunks_exact(2)
+            .map(|pair| Self::hash_pair(pair[0], pair[1]))
+            .collect()
+    }
+}
+
+/// SipHash through `DefaultHasher`, the hasher of trees that aren't given another one.
+///
+/// `DefaultHasher::new()` only seeds SipHash with fixed keys on the stack, there is no allocation.
+/// It can't be reset, and cloning a prepared hasher, even one kept in a thread local, benchmarks
+/// about the same as creating a new one (see benches/verify.rs), so every hash simply gets a
+/// fresh hasher.
+#[derive(Debug, Clone, Default)]
+pub struct DefaultMerkleHasher(DefaultHasher);
+
+impl MerkleHasher for DefaultMerkleHasher {
+    const ALGORITHM: u8 = 1;
+    /// The hash of no bytes as `DefaultHasher` computes it with the current toolchain. The
+    /// standard library may change its algorithm in any release, so this holds for the toolchain
+    /// it was computed with, which the tests check, and isn't a canonical value.
+    const EMPTY_ROOT: Hash = Hash::new(0xd1fb_a762_150c_532c);
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes);
+    }
+
+    fn finish(self) -> Hash {
+        Hash::from(self.0.finish())
+    }
+}
+
+/// SHA-256, for trees whose proofs are checked by verifiers that only trust standard hashes.
+///
+/// Setting it up only copies its initial state, so a thread local hasher reset as it finishes
+/// benchmarks the same as a new one (see benches/compress.rs), and every hash gets a fresh hasher
+/// too.
+#[cfg(feature = "sha2")]
+#[derive(Debug, Clone, Default)]
+pub struct Sha256MerkleHasher(sha2::Sha256);
+
+// The initial state of SHA-256, which the block level API leaves to the caller
+#[cfg(feature = "sha2-direct-compress")]
+const SHA256_IV: [u32; 8] = [
+    0x6a09_e667,
+    0xbb67_ae85,
+    0x3c6e_f372,
+    0xa54f_f53a,
+    0x510e_527f,
+    0x9b05_688c,
+    0x1f83_d9ab,
+    0x5be0_cd19,
+];
+
+#[cfg(feature = "sha2")]
+impl MerkleHasher for Sha256MerkleHasher {
+    const ALGORITHM: u8 = 2;
+    const EMPTY_ROOT: Hash = Hash::new(0xe3b0_c442_98fc_1c14);
+
+    fn write(&mut self, bytes: &[u8]) {
+        sha2::Digest::update(&mut self.0, bytes);
+    }
+
+    fn finish(self) -> Hash {
+        let digest = sha2::Digest::finalize(self.0);
+        let mut hash = [0; 8];
+        hash.copy_from_slice(&digest[..8]);
+        Hash::from_be_bytes(hash)
+    }
+
+    // Direct compression, with no SIMD: a pair is 16 bytes, so its message is always one block with
+    // the same padding, which goes straight to the compression function without the hasher's
+    // buffering and finalization. Blocks are still compressed one at a time
+    #[cfg(feature = "sha2-direct-compress")]
+    fn hash_each_pair(nodes: &[Hash]) -> Vec<Hash> {
+        nodes
+            .chunks_exact(2)
+            .map(|pair| {
+                let mut block = [0; 64];
+                block[..8].copy_from_slice(&pair[0].to_be_bytes());
+                block[8..16].copy_from_slice(&pair[1].to_be_bytes());
+                block[16] = 0x80;
+                block[56..].copy_from_slice(&128u64.to_be_bytes());
+                let mut state = SHA256_IV;
+                sha2::block_api::compress256(&mut state, slice::from_ref(&block));
+                Hash::new(u64::from(state[0]) << 32 | u64::from(state[1]))
+            })
+            .collect()
+    }
+}
+
+/// SHA-256 with the leaves and the nodes told apart by a prefix, for roots that other
+/// implementations must reproduce, see [`crate::merkle_tree::MerkleTree::new_canonical`].
+#[cfg(feature = "sha2")]
+#[derive(Debug, Clone, Default)]
+pub struct CanonicalMerkleHasher(Sha256MerkleHasher);
+
+#[cfg(feature = "sha2")]
+const LEAF_PREFIX: u8 = 0x00;
+#[cfg(feature = "sha2")]
+const NODE_PREFIX: u8 = 0x01;
+
+#[cfg(feature = "sha2")]
+impl MerkleHasher for CanonicalMerkleHasher {
+    const ALGORITHM: u8 = 3;
+    const EMPTY_ROOT: Hash = Sha256MerkleHasher::EMPTY_ROOT;
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes);
+    }
+
+    fn finish(self) -> Hash {
+        self.0.finish()
+    }
+
+    fn leaf_hasher() -> Self {
+        let mut hasher = Self::default();
+        hasher.write(&[LEAF_PREFIX]);
+        hasher
+    }
+
+    fn hash_pair(left: Hash, right: Hash) -> Hash {
+        let mut hasher = Self::default();
+        hasher.write(&[NODE_PREFIX]);
+        hasher.write(&left.to_be_bytes());
+        hasher.write(&right.to_be_bytes());
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use crate::hasher::{DefaultMerkleHasher, MerkleHasher};
+
+    #[test]
+    fn a_hasher_empty_root_is_the_hash_of_no_bytes() {
+        // DefaultHasher may change with the toolchain, which this catches
+        assert_eq!(
+            DefaultMerkleHasher::default().finish(),
+            DefaultMerkleHasher::EMPTY_ROOT
+        );
+
+        #[cfg(feature = "sha2")]
+        {
+            use crate::hasher::Sha256MerkleHasher;
+
+            assert_eq!(
+                Sha256MerkleHasher::default().finish(),
+                Sha256MerkleHasher::EMPTY_ROOT
+            );
+
+            use crate::hasher::CanonicalMerkleHasher;
+
+            assert_eq!(
+                CanonicalMerkleHasher::default().finish(),
+                CanonicalMerkleHasher::EMPTY_ROOT
+            );
+        }
+    }
+
+    #[cfg(feature = "sha2-direct-compress")]
+    #[test]
+    fn directly_compressed_pairs_hash_like_the_hasher() {
+        use crate::hash::Hash;
+        use crate::hasher::Sha256MerkleHasher;
+
+        let nodes: Vec<Hash> = (0..(1 << 12) + 1)
+            .map(|node| Hash::from(node * 7919))
+            .collect();
+        let scalar: Vec<Hash> = nodes
+            .chunks_exact(2)
+            .map(|pair| Sha256MerkleHasher::hash_pair(pair[0], pair[1]))
+            .collect();
+
+        assert_eq!(Sha256MerkleHasher::hash_each_pair(&nodes), scalar);
+    }
+}
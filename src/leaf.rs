@@ -0,0 +1,166 @@
+/// How a transaction becomes the bytes its leaf hash is computed from.
+///
+/// Unlike `std::hash::Hash`, the encoding is part of the tree's format: integers are big endian
+/// whatever the platform, and strings and byte slices are their bytes, so a leaf is hashed from
+/// the same bytes on every platform. The leaf hash is only as stable as the hasher though: the
+/// default one is `DefaultHasher`, which may change between Rust releases, while the roots of
+/// `MerkleTree::new_canonical`, behind the `sha2` feature, can be reproduced in other languages.
+pub trait LeafEncode {
+    fn encode(&self) -> Vec<u8>;
+}
+
+impl<T: LeafEncode + ?Sized> LeafEncode for &T {
+    fn encode(&self) -> Vec<u8> {
+        (**self).encode()
+    }
+}
+
+// Lets a tree mix leaves of different types as `Box<dyn LeafEncode>`
+impl<T: LeafEncode + ?Sized> LeafEncode for Box<T> {
+    fn encode(&self) -> Vec<u8> {
+        (**self).encode()
+    }
+}
+
+impl LeafEncode for [u8] {
+    fn encode(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl<const N: usize> LeafEncode for [u8; N] {
+    fn encode(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl LeafEncode for Vec<u8> {
+    fn encode(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl LeafEncode for str {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl LeafEncode for String {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl LeafEncode for bool {
+    fn encode(&self) -> Vec<u8> {
+        vec![u8::from(*self)]
+    }
+}
+
+macro_rules! impl_leaf_encode_for_integers {
+    ($($integer:ty),*) => {
+        $(
+            impl LeafEncode for $integer {
+                fn encode(&self) -> Vec<u8> {
+                    self.to_be_bytes().to_vec()
+                }
+            }
+        )*
+    };
+}
+
+impl_leaf_encode_for_integers!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+// Their width depends on the platform, so they are always encoded as 64 bits
+impl LeafEncode for usize {
+    fn encode(&self) -> Vec<u8> {
+        (*self as u64).encode()
+    }
+}
+
+impl LeafEncode for isize {
+    fn encode(&self) -> Vec<u8> {
+        (*self as i64).encode()
+    }
+}
+
+// Every part goes after its length, as a big endian u64, so ("ab", "c") and ("a", "bc") differ
+impl<A: LeafEncode, B: LeafEncode> LeafEncode for (A, B) {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for part in [self.0.encode(), self.1.encode()] {
+            bytes.extend_from_slice(&part.len().encode());
+            bytes.extend_from_slice(&part);
+        }
+        bytes
+    }
+}
+
+/// Encodes any `std::hash::Hash` type as its `DefaultHasher` hash, for convenience where the
+/// leaves only need to be proven within the same build.
+#[cfg(feature = "std-hash")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StdHashed<T>(pub T);
+
+#[cfg(feature = "std-hash")]
+impl<T: std::hash::Hash> LeafEncode for StdHashed<T> {
+    fn encode(&self) -> Vec<u8> {
+        use std::hash::{DefaultHasher, Hasher};
+
+        let mut hasher = DefaultHasher::new();
+        self.0.hash(&mut hasher);
+        hasher.finish().encode()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use crate::leaf::LeafEncode;
+    use crate::merkle_tree::{LeafIndex, MerkleTree};
+
+    #[test]
+    fn integers_are_encoded_as_big_endian_bytes() {
+        assert_eq!(258u16.encode(), [1, 2]);
+        assert_eq!((-2i32).encode(), [0xff, 0xff, 0xff, 0xfe]);
+        assert_eq!(7usize.encode(), 7u64.encode());
+        assert_eq!("AB".encode(), String::from("AB").encode());
+
+        assert_eq!(
+            MerkleTree::leaf_hash(&0x0102_0304u32),
+            MerkleTree::leaf_hash(&[1u8, 2, 3, 4])
+        );
+        assert_eq!(MerkleTree::leaf_hash(&5usize), MerkleTree::leaf_hash(&5u64));
+        assert_ne!(MerkleTree::leaf_hash(&5u32), MerkleTree::leaf_hash(&5u64));
+        assert_ne!(("ab", "c").encode(), ("a", "bc").encode());
+    }
+
+    #[cfg(feature = "std-hash")]
+    #[test]
+    fn any_hashable_type_can_be_a_leaf_through_its_std_hash() {
+        use crate::leaf::StdHashed;
+
+        let transactions = vec![StdHashed(('a', 1)), StdHashed(('b', 2))];
+        let merkle_tree = MerkleTree::new(transactions).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(1)).unwrap();
+
+        assert!(merkle_tree.verify_iter(StdHashed(('b', 2)), proof));
+    }
+
+    #[test]
+    fn leaves_of_different_types_can_share_a_tree() {
+        let transactions: Vec<Box<dyn LeafEncode>> =
+            vec![Box::new(String::from("A")), Box::new(7u64), Box::new(true)];
+        let merkle_tree = MerkleTree::new(transactions).unwrap();
+
+        let proof = merkle_tree.proof_for(LeafIndex(0)).unwrap();
+        let string: Box<dyn LeafEncode> = Box::new("A");
+        assert!(merkle_tree.verify_ref(&string, &proof));
+        let proof = merkle_tree.proof_for(LeafIndex(1)).unwrap();
+        let integer: Box<dyn LeafEncode> = Box::new(7u64);
+        assert!(merkle_tree.verify_ref(&integer, &proof));
+        let integer: Box<dyn LeafEncode> = Box::new(7u32);
+        assert!(!merkle_tree.verify_ref(&integer, &proof));
+    }
+}
@@ -1 +1,13 @@
+pub mod batch;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+pub mod config;
+pub mod error;
+pub mod forest;
+pub mod hash;
+pub mod hasher;
+pub mod leaf;
+pub mod map;
 pub mod merkle_tree;
+pub mod proof;
+pub mod tree_head;
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::hash::Hash as StdHash;
+
+use crate::error::MerkleError;
+use crate::leaf::LeafEncode;
+use crate::merkle_tree::{LeafIndex, MerkleTree};
+use crate::proof::Proof;
+
+/// A tree over `(key, value)` leaves that finds them by key, so values can be served along with a
+/// proof that they are the ones committed to, as an authenticated dictionary.
+///
+/// Keys are expected to be unique. If a key is repeated in the pairs it is built from, the last
+/// pair with it is the one found.
+pub struct MerkleMap<K, V>
+where
+    (K, V): LeafEncode,
+{
+    merkle_tree: MerkleTree<(K, V)>,
+    indices: HashMap<K, LeafIndex>,
+}
+
+impl<K, V> MerkleMap<K, V>
+where
+    (K, V): LeafEncode,
+    K: Eq + StdHash + Clone,
+    V: Clone,
+{
+    pub fn new(pairs: Vec<(K, V)>) -> Result<Self, MerkleError> {
+        let indices = pairs
+            .iter()
+            .enumerate()
+            .map(|(index, (key, _))| (key.clone(), LeafIndex(index)))
+            .collect();
+        Ok(Self {
+            merkle_tree: MerkleTree::new(pairs)?,
+            indices,
+        })
+    }
+
+    /// Replaces the value of a key already in the map, returning the one it had, or adds the pair
+    /// as a new leaf, like [`HashMap::insert`]. Either way the root changes.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, MerkleError> {
+        match self.indices.get(&key) {
+            Some(&index) => {
+                let old_value = self.get(&key).cloned();
+                self.merkle_tree.update(index, (key, value))?;
+                Ok(old_value)
+            }
+            None => {
+                let index = self.merkle_tree.add((key.clone(), value))?;
+                self.indices.insert(key, index);
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let (_, value) = self.merkle_tree.get_leaf(*self.indices.get(key)?)?;
+        Some(value)
+    }
+
+    pub fn get_proof_by_key(&self, key: &K) -> Option<(V, Proof)> {
+        let index = *self.indices.get(key)?;
+        let value = self.get(key)?.clone();
+        Some((value, self.merkle_tree.detached_proof(index)?))
+    }
+
+    pub fn merkle_tree(&self) -> &MerkleTree<(K, V)> {
+        &self.merkle_tree
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use crate::map::MerkleMap;
+    use crate::merkle_tree::MerkleTree;
+
+    #[test]
+    fn a_merkle_map_proves_the_value_of_a_key() {
+        let mut merkle_map = MerkleMap::new(vec![
+            (String::from("alice"), 10u64),
+            (String::from("bob"), 20u64),
+            (String::from("carol"), 30),
+        ])
+        .unwrap();
+        merkle_map.insert(String::from("dave"), 40u64).unwrap();
+        merkle_map.insert(String::from("bob"), 25u64).unwrap();
+        let root = merkle_map.merkle_tree().root();
+
+        let (value, proof) = merkle_map.get_proof_by_key(&String::from("bob")).unwrap();
+        assert_eq!(value, 25);
+        assert_eq!(
+            MerkleTree::verify_detached(&(String::from("bob"), 25u64), &proof, root),
+            Ok(true)
+        );
+        assert_eq!(
+            MerkleTree::verify_detached(&(String::from("bob"), 20u64), &proof, root),
+            Ok(false)
+        );
+
+        let (value, proof) = merkle_map.get_proof_by_key(&String::from("dave")).unwrap();
+        assert_eq!(value, 40);
+        assert_eq!(
+            MerkleTree::verify_detached(&(String::from("dave"), 40u64), &proof, root),
+            Ok(true)
+        );
+        assert!(merkle_map.get_proof_by_key(&String::from("erin")).is_none());
+    }
+
+    #[test]
+    fn a_merkle_map_inserts_and_overwrites_values_like_a_hash_map() {
+        let mut merkle_map = MerkleMap::new(vec![(1u32, 10u64), (2, 20)]).unwrap();
+        assert_eq!(merkle_map.get(&1), Some(&10));
+        assert_eq!(merkle_map.get(&3), None);
+
+        let root = merkle_map.merkle_tree().root();
+        assert_eq!(merkle_map.insert(3, 30), Ok(None));
+        assert_eq!(merkle_map.get(&3), Some(&30));
+        assert_ne!(merkle_map.merkle_tree().root(), root);
+
+        let (_, old_proof) = merkle_map.get_proof_by_key(&2).unwrap();
+        let root = merkle_map.merkle_tree().root();
+        assert_eq!(merkle_map.insert(2, 25), Ok(Some(20)));
+        assert_eq!(merkle_map.get(&2), Some(&25));
+        let new_root = merkle_map.merkle_tree().root();
+        assert_ne!(new_root, root);
+        assert_eq!(
+            MerkleTree::verify_detached(&(2u32, 20u64), &old_proof, new_root),
+            Ok(false)
+        );
+        assert_eq!(merkle_map.merkle_tree().leaf_hashes().len(), 3);
+    }
+}
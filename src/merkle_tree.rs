@@ -1,300 +1,4281 @@
-use std::hash::{DefaultHasher, Hash, Hasher};
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::io::{ErrorKind, Read};
+use std::iter;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
 
+use crate::batch::{ProofBatcher, QuorumProof};
+use crate::config::{MerkleConfig, OddStrategy};
+use crate::error::{MerkleError, StructuralError};
+use crate::hash::Hash;
+#[cfg(feature = "sha2")]
+use crate::hasher::CanonicalMerkleHasher;
+use crate::hasher::{DefaultMerkleHasher, MerkleHasher};
+use crate::leaf::LeafEncode;
+use crate::proof::{
+    expand_proof_with, fold_proof, fold_proof_with, proof_index, CompactProof, Proof, ProofBundle,
+};
+use crate::tree_head::{DatasetCommitment, TreeHead};
+
+/// A node hashed with the one being folded, on its left or on its right.
+///
+/// A lone node at the end of a level is paired with itself, so its sibling is a `Right` holding
+/// its own hash: the third leaf of a tree of three is proven by `Right(C)` and then `Left(AB)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SiblingHash {
-    Left(u64),
-    Right(u64),
+    Left(Hash),
+    Right(Hash),
+}
+
+/// The old name of [`SiblingHash`], kept for one release so code importing it still builds.
+#[deprecated(note = "renamed to `SiblingHash`")]
+pub type SiblingsHash = SiblingHash;
+
+/// Position of a leaf inside the tree, kept apart from plain counts so the two can't be swapped.
+///
+/// ```
+/// use merkle_tree::merkle_tree::{LeafIndex, MerkleTree};
+///
+/// let merkle_tree = MerkleTree::new(vec!["A", "B"]).unwrap();
+/// assert_eq!(merkle_tree.get_leaf(LeafIndex(1)), Some(&"B"));
+/// ```
+///
+/// A bare `usize` is not accepted where a leaf position is expected:
+///
+/// ```compile_fail
+/// use merkle_tree::merkle_tree::MerkleTree;
+///
+/// let merkle_tree = MerkleTree::new(vec!["A", "B"]).unwrap();
+/// merkle_tree.get_leaf(1usize);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LeafIndex(pub usize);
+
+impl From<usize> for LeafIndex {
+    fn from(index: usize) -> Self {
+        Self(index)
+    }
+}
+
+/// Outcome of [`MerkleTree::verify_diagnostic`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofDiagnostic {
+    Valid,
+    /// The proof does not verify as given, but it does once its siblings are read in reverse order.
+    Reversed,
+    Invalid,
+}
+
+/// Outcome of [`MerkleTree::verify_explain`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyOutcome {
+    Valid,
+    /// The proof folds to `computed` instead, which the caller can compare with other candidate
+    /// roots to tell a leaf that isn't a member from a proof checked against the wrong tree.
+    ComputedRootMismatch {
+        computed: Hash,
+        expected: Hash,
+    },
+}
+
+/// Something about the leaves a tree was built from that is worth tuning, see
+/// [`MerkleTree::new_with_warnings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildWarning {
+    /// Lone nodes were padded `padded_nodes` times over all the levels, which makes the tree
+    /// unbalanced and hashes nodes that commit to nothing new. A batch of `suggested_leaves`, the
+    /// next power of the arity, would need no padding.
+    Padding {
+        leaves: usize,
+        padded_nodes: usize,
+        suggested_leaves: usize,
+    },
+}
+
+/// Proves a value is not in a sorted tree with the leaves right below and above it, along with
+/// their proofs. A side is `None` when the value falls before the first or after the last leaf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbsenceProof<H> {
+    pub lower: Option<(H, Vec<SiblingHash>)>,
+    pub upper: Option<(H, Vec<SiblingHash>)>,
+}
+
+/// Outcome of [`MerkleTree::mutation_impact`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofImpact {
+    /// The leaves whose proofs would have a different sibling, in order.
+    pub invalidated: Vec<LeafIndex>,
 }
 
-#[derive(Clone)]
-struct MerkleNode {
-    hash_value: u64,
-    left_son: Option<Box<MerkleNode>>,
-    right_son: Option<Box<MerkleNode>>,
+/// A leaf with its position and its proof, the whole evidence that it is in a tree, see
+/// [`MerkleTree::witness`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Witness<H> {
+    pub leaf: H,
+    pub index: LeafIndex,
+    pub proof: Vec<SiblingHash>,
 }
-pub struct MerkleTree<H: Hash + Clone> {
-    merkle_root: MerkleNode,
+
+/// Proves two leaves hold the same value without revealing it, with the index, the leaf hash and
+/// the proof of each, see [`MerkleTree::equality_proof`]. A salted tree keeps leaves from a small
+/// domain from being found by their leaf hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EqualityProof {
+    pub first: (LeafIndex, Hash, Vec<SiblingHash>),
+    pub second: (LeafIndex, Hash, Vec<SiblingHash>),
+}
+
+// The hashes of every level of the tree, from the leaves up to the root. A lone node at the end
+// of a level is paired with itself, so every level has half the nodes of the one below, rounded up.
+// Nodes are already linked by index, the sons of `i` being from `arity * i` in the level below, so
+// there are no boxed nodes to chase: a proof reads one contiguous vector per level
+type Levels = Vec<Vec<Hash>>;
+
+pub struct MerkleTree<H: LeafEncode, M: MerkleHasher = DefaultMerkleHasher> {
+    // Mutations other than add only empty this, the first reader afterwards rebuilds the tree once.
+    // Shared with snapshots, so add only copies the levels while a snapshot still holds them
+    levels: OnceLock<Result<Arc<Levels>, MerkleError>>,
+    leaf_hashes: Vec<Hash>,
+    // None when the tree was built from leaf hashes or pruned, so there are no values to keep
+    leafs: Option<Vec<H>>,
+    // The index in leafs of the value at every tree position, when the tree was built with
+    // new_with_order. Leafs then keep the caller's order
+    order: Option<Vec<usize>>,
+    // Number of times the whole tree was rebuilt, so batched edits can be checked to pay only one
+    rebuilds: AtomicUsize,
+    version: u64,
+    // Proofs by leaf hash, see get_proof_cached for how the lock is used
+    proof_cache: Mutex<HashMap<Hash, Vec<SiblingHash>>>,
+    // First index of every leaf hash, built on the first lookup when the config asks for it and
+    // emptied along with the levels
+    leaf_index: OnceLock<HashMap<Hash, usize>>,
+    root_listener: Option<RootListener>,
+    // The tree as it was when begin was called, restored by rollback
+    staged: Option<Staged<H>>,
+    config: MerkleConfig,
+    hasher: PhantomData<fn() -> M>,
+}
+
+// The root the callback last saw, None while the leaves are left empty
+struct RootListener {
+    callback: Box<dyn FnMut(Hash) + Send + Sync>,
+    root: Option<Hash>,
+}
+
+// The levels are shared with the tree until the first staged mutation drops them
+struct Staged<H> {
+    levels: Option<Result<Arc<Levels>, MerkleError>>,
+    leaf_hashes: Vec<Hash>,
+    leafs: Option<Vec<H>>,
+    order: Option<Vec<usize>>,
+}
+
+/// Mutable access to the leaves of a tree, which is rebuilt once the next time it is read.
+///
+/// Leaving the leaves empty is not caught here, the next read fails instead, which the `try_`
+/// methods like [`MerkleTree::try_get_proof`] report.
+pub struct LeavesGuard<'a, H: LeafEncode, M: MerkleHasher = DefaultMerkleHasher> {
+    merkle_tree: &'a mut MerkleTree<H, M>,
     leafs: Vec<H>,
 }
 
-impl MerkleNode {
-    pub fn new(
-        hash_value: u64,
-        left_son: Option<Box<MerkleNode>>,
-        right_son: Option<Box<MerkleNode>>,
-    ) -> Self {
-        Self {
-            hash_value,
-            left_son,
-            right_son,
+/// Verifies the leaf at one position again and again without building its proof every time, see
+/// [`MerkleTree::prepare_verifier`].
+///
+/// It borrows the tree, so the tree can't be mutated while the verifier is alive, which would
+/// leave it checking against a stale root:
+///
+/// ```compile_fail
+/// use merkle_tree::merkle_tree::{LeafIndex, MerkleTree};
+///
+/// let mut merkle_tree = MerkleTree::new(vec!["A", "B"]).unwrap();
+/// let verifier = merkle_tree.prepare_verifier(LeafIndex(1)).unwrap();
+/// merkle_tree.add("C").unwrap();
+/// verifier.verify(&"B");
+/// ```
+pub struct PreparedVerifier<'a, H: LeafEncode, M: MerkleHasher = DefaultMerkleHasher> {
+    merkle_tree: &'a MerkleTree<H, M>,
+    siblings: Vec<SiblingHash>,
+    root: Hash,
+}
+
+impl<H: LeafEncode, M: MerkleHasher> PreparedVerifier<'_, H, M> {
+    pub fn verify(&self, transaction: &H) -> bool {
+        let leaf_hash = self.merkle_tree.hash_leaf(transaction);
+        fold_strict::<M, _>(&self.merkle_tree.config, leaf_hash, &self.siblings) == Some(self.root)
+    }
+}
+
+/// The nodes of a tree frozen by [`MerkleTree::snapshot`], serving proofs against the root the
+/// tree had then, however it changed afterwards. The nodes are shared with the tree until it is
+/// mutated, so taking a snapshot copies nothing.
+///
+/// ```
+/// use merkle_tree::merkle_tree::{LeafIndex, MerkleTree};
+///
+/// let mut merkle_tree = MerkleTree::new(vec!["A", "B"]).unwrap();
+/// let snapshot = merkle_tree.snapshot().unwrap();
+/// merkle_tree.add("C").unwrap();
+/// let proof = snapshot.proof(LeafIndex(1)).unwrap();
+/// assert!(snapshot.verify(&"B", &proof));
+/// ```
+pub struct RootSnapshot<H: LeafEncode, M: MerkleHasher = DefaultMerkleHasher> {
+    levels: Arc<Levels>,
+    config: MerkleConfig,
+    leaf: PhantomData<fn(&H) -> M>,
+}
+
+impl<H: LeafEncode, M: MerkleHasher> Clone for RootSnapshot<H, M> {
+    fn clone(&self) -> Self {
+        Self {
+            levels: Arc::clone(&self.levels),
+            config: self.config,
+            leaf: PhantomData,
+        }
+    }
+}
+
+impl<H: LeafEncode, M: MerkleHasher> RootSnapshot<H, M> {
+    pub fn root(&self) -> Hash {
+        self.levels[self.levels.len() - 1]
+            .first()
+            .copied()
+            .unwrap_or(M::EMPTY_ROOT)
+    }
+
+    /// The number of leaves the tree had when the snapshot was taken.
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels[0].is_empty()
+    }
+
+    /// The proof of the leaf at `index` against [`RootSnapshot::root`], `None` past the last leaf
+    /// the tree had then.
+    pub fn proof(&self, index: LeafIndex) -> Option<Vec<SiblingHash>> {
+        if index.0 >= self.len() {
+            return None;
+        }
+        let mut proof = Vec::new();
+        write_proof::<M>(&self.levels, &self.config, index.0, &mut proof);
+        Some(proof)
+    }
+
+    pub fn verify(&self, transaction: &H, proof: &[SiblingHash]) -> bool {
+        let leaf_hash = MerkleTree::<H, M>::configured_leaf_hash(transaction, &self.config);
+        !self.is_empty() && fold_strict::<M, _>(&self.config, leaf_hash, proof) == Some(self.root())
+    }
+}
+
+// Functions that don't take a tree can't infer its hasher, so like `HashMap::new` they are only
+// given for the default one, trees with other hashers are built with `with_hasher`
+impl<H: LeafEncode> MerkleTree<H> {
+    pub fn new(transactions: Vec<H>) -> Result<Self, MerkleError> {
+        Self::with_config(transactions, MerkleConfig::default())
+    }
+
+    /// Like [`MerkleTree::new`], but also returns the leaf hashes computed while building, for
+    /// callers that index them, so they don't have to hash every transaction again.
+    pub fn new_with_leaf_hashes(transactions: Vec<H>) -> Result<(Self, Vec<Hash>), MerkleError> {
+        let merkle_tree = Self::new(transactions)?;
+        let leaf_hashes = merkle_tree.leaf_hashes.clone();
+        Ok((merkle_tree, leaf_hashes))
+    }
+
+    /// Builds a tree with `transactions[order[i]]` at position `i`, for interop with systems that
+    /// order the leaves by something other than their values. [`MerkleTree::leaves`] keeps the
+    /// order of `transactions`, while [`MerkleTree::proof_for`], [`MerkleTree::get_leaf`] and the
+    /// other methods taking a [`LeafIndex`] use tree positions.
+    ///
+    /// [`MerkleTree::add`], [`MerkleTree::update`] and [`MerkleTree::remove_all`] keep the two
+    /// orders apart. Mutations that reorder the leaves, like [`MerkleTree::insert_sorted`] or
+    /// [`MerkleTree::leaves_mut`], first put the values in tree order, which `leaves` follows
+    /// from then on.
+    ///
+    /// Fails with [`MerkleError::BadOrder`] unless `order` is a permutation of the positions.
+    pub fn new_with_order(transactions: Vec<H>, order: Vec<usize>) -> Result<Self, MerkleError> {
+        Self::create_ordered_tree(transactions, order, MerkleConfig::default())
+    }
+
+    /// Like [`MerkleTree::new`], but also returns warnings about the leaves, such as a count just
+    /// past a power of two that needs a lot of padding, to help tune batch sizes.
+    pub fn new_with_warnings(
+        transactions: Vec<H>,
+    ) -> Result<(Self, Vec<BuildWarning>), MerkleError> {
+        let merkle_tree = Self::new(transactions)?;
+        let config = merkle_tree.config;
+        let leaves = merkle_tree.leaf_hashes.len();
+        let padded_nodes = config.padded_nodes(leaves);
+        let mut warnings = Vec::new();
+        if padded_nodes > 0 {
+            let mut suggested_leaves = 1;
+            while suggested_leaves < leaves {
+                suggested_leaves *= config.arity;
+            }
+            warnings.push(BuildWarning::Padding {
+                leaves,
+                padded_nodes,
+                suggested_leaves,
+            });
+        }
+        Ok((merkle_tree, warnings))
+    }
+
+    /// Builds a tree and returns its root with the proof of every leaf, in order, for callers that
+    /// commit once and hand out the proofs, without keeping the tree around.
+    pub fn build_with_all_proofs(
+        transactions: Vec<H>,
+    ) -> Result<(Hash, Vec<Vec<SiblingHash>>), MerkleError> {
+        let merkle_tree = Self::new(transactions)?;
+        let levels = merkle_tree.ensure_built()?;
+        let proofs = (0..merkle_tree.leaf_hashes.len())
+            .map(|index| {
+                let mut proof = Vec::with_capacity(levels.len() - 1);
+                merkle_tree.write_proof(levels, index, &mut proof);
+                proof
+            })
+            .collect();
+        Ok((merkle_tree.root(), proofs))
+    }
+
+    /// Builds a tree over leaves the caller guarantees are sorted and unique, so
+    /// [`MerkleTree::proof_of_absence`] can be used without inserting them one by one through
+    /// [`MerkleTree::insert_sorted`].
+    ///
+    /// The order is only checked in debug builds. Leaves out of order or repeated make absence
+    /// proofs wrong: an absent value can be missed, and a present one proven absent.
+    pub fn from_sorted_unchecked(transactions: Vec<H>) -> Result<Self, MerkleError>
+    where
+        H: Ord,
+    {
+        debug_assert!(
+            transactions.windows(2).all(|pair| pair[0] < pair[1]),
+            "the leaves are not sorted and unique"
+        );
+        Self::new(transactions)
+    }
+
+    /// Like [`MerkleTree::new`], but an empty tree is allowed and has [`MerkleTree::EMPTY_ROOT`]
+    /// as its root.
+    pub fn allow_empty(transactions: Vec<H>) -> Result<Self, MerkleError> {
+        Self::with_config(transactions, MerkleConfig::default().allow_empty(true))
+    }
+
+    pub fn with_config(transactions: Vec<H>, config: MerkleConfig) -> Result<Self, MerkleError> {
+        Self::with_hasher(transactions, config)
+    }
+
+    /// Builds a tree whose leaf hashes are mixed with a random salt, so leaves from a small domain
+    /// (booleans, small numbers...) can't be found by hashing every candidate. Proofs carry the
+    /// salt, see [`MerkleTree::detached_proof`], and only verify against this tree.
+    pub fn new_salted(transactions: Vec<H>) -> Result<Self, MerkleError> {
+        Self::with_salt(transactions, RandomState::new().build_hasher().finish())
+    }
+
+    pub fn with_salt(transactions: Vec<H>, salt: u64) -> Result<Self, MerkleError> {
+        Self::with_config(transactions, MerkleConfig::default().salt(salt))
+    }
+
+    /// Builds a tree over leaves that are already hashed, such as the roots of other trees.
+    /// No leaf values are kept, so value lookups like [`MerkleTree::get_leaf`] find nothing.
+    pub fn from_leaf_hashes(leaf_hashes: Vec<Hash>) -> Result<Self, MerkleError> {
+        Self::build_from_leaf_hashes(leaf_hashes, MerkleConfig::default())
+    }
+
+    /// Rebuilds a tree with the default config from the output of [`MerkleTree::to_array`] without
+    /// hashing it again, like [`MerkleTree::from_leaf_hashes`] no leaf values are kept.
+    pub fn from_array(hashes: Vec<Hash>, leaf_count: usize) -> Result<Self, MerkleError> {
+        if leaf_count == 0 {
+            return Err(MerkleError::EmptyTransactions);
+        }
+        // Lone nodes are duplicated, so the tree is always perfect over the next power of two
+        let expected = 2 * leaf_count.next_power_of_two() - 1;
+        if hashes.len() != expected {
+            return Err(MerkleError::BadArrayLength {
+                expected,
+                found: hashes.len(),
+            });
+        }
+
+        // Every level is the prefix of its row in the array, the rest of the row is padding
+        let mut levels = Vec::new();
+        let mut level_len = leaf_count;
+        let mut row_start = leaf_count.next_power_of_two() - 1;
+        loop {
+            levels.push(hashes[row_start..row_start + level_len].to_vec());
+            if level_len == 1 {
+                break;
+            }
+            level_len = level_len.div_ceil(2);
+            row_start /= 2;
+        }
+
+        Ok(Self::from_levels(levels, MerkleConfig::default()))
+    }
+
+    /// The hash of a leaf in an unsalted tree.
+    pub fn leaf_hash(transaction: &H) -> Hash {
+        Self::salted_leaf_hash(transaction, None)
+    }
+
+    /// Proofs don't say how many times their tree hashes its leaves, so the leaf is hashed once,
+    /// and proofs from a tree configured with [`MerkleConfig::iterations`] only verify with
+    /// [`MerkleTree::verify_versioned`] on that tree.
+    pub fn verify_detached(
+        transaction: &H,
+        proof: &Proof,
+        root: Hash,
+    ) -> Result<bool, MerkleError> {
+        Self::verify_detached_proof(transaction, proof, root, 1)
+    }
+
+    pub fn verify_with_tree_head(
+        transaction: &H,
+        proof: &[SiblingHash],
+        tree_head: &TreeHead,
+    ) -> bool {
+        Self::fold_transaction(transaction, proof.iter()) == tree_head.root
+    }
+
+    /// Like [`MerkleTree::verify_with_tree_head`], but also returns every hash folded along the
+    /// way for auditors to log: the leaf hash, then one per sibling, ending with the computed root.
+    pub fn verify_trace(transaction: H, proof: &[SiblingHash], root: Hash) -> (bool, Vec<Hash>) {
+        let mut trace = Vec::with_capacity(proof.len() + 1);
+        trace.push(Self::leaf_hash(&transaction));
+        for sibling_hash in proof {
+            trace.push(fold_proof(trace[trace.len() - 1], [sibling_hash]));
+        }
+        (trace[trace.len() - 1] == root, trace)
+    }
+
+    pub fn verify_explain(transaction: &H, proof: &[SiblingHash], root: Hash) -> VerifyOutcome {
+        let computed = Self::fold_transaction(transaction, proof);
+        if computed == root {
+            VerifyOutcome::Valid
+        } else {
+            VerifyOutcome::ComputedRootMismatch {
+                computed,
+                expected: root,
+            }
+        }
+    }
+
+    /// The index of the first of `roots` the proof folds to, to tell which version of a tree, among
+    /// those replicas are at, it was issued for. The proof is only folded once.
+    pub fn matching_root(transaction: H, proof: &[SiblingHash], roots: &[Hash]) -> Option<usize> {
+        let computed = Self::fold_transaction(&transaction, proof);
+        roots.iter().position(|root| *root == computed)
+    }
+
+    /// Checks `root` commits to exactly this multiset of leaves, in any order, for roots built over
+    /// the leaves sorted, like [`MerkleTree::from_sorted_unchecked`] or
+    /// [`MerkleTree::insert_sorted`] do, and [`MerkleTree::try_sorted_root`] gives for a tree with
+    /// the default config. An empty set never verifies.
+    pub fn verify_set(leaves: &[H], root: Hash) -> bool
+    where
+        H: Ord + Clone,
+    {
+        let mut sorted = leaves.to_vec();
+        sorted.sort();
+        Self::new(sorted).is_ok_and(|merkle_tree| merkle_tree.root() == root)
+    }
+
+    /// Like [`MerkleTree::verify_at`], for a verifier that only knows the tree head of a tree built
+    /// with the default config.
+    pub fn verify_with_size(
+        transaction: &H,
+        index: LeafIndex,
+        proof: &[SiblingHash],
+        tree_head: &TreeHead,
+    ) -> Result<bool, MerkleError> {
+        if index.0 >= tree_head.size {
+            return Err(MerkleError::IndexOutOfBounds(index.0));
+        }
+        let depth = MerkleConfig::default().proof_length(tree_head.size, index.0);
+        if proof.len() != depth {
+            return Err(MerkleError::WrongDepth {
+                expected: depth,
+                got: proof.len(),
+            });
+        }
+        Ok(proof_index(proof) == Some(index.0)
+            && Self::verify_with_tree_head(transaction, proof, tree_head))
+    }
+
+    /// Verifies a leaf hash for a light client that knows only the root and the size of a tree
+    /// built with the default config, binding the proof to both: `index` must be below `size`,
+    /// the proof as long as that leaf is deep, and its sides must point at `index` before it is
+    /// folded and compared with `root`.
+    pub fn verify_leaf_hash_at(
+        leaf_hash: Hash,
+        index: LeafIndex,
+        proof: &[SiblingHash],
+        root: Hash,
+        size: usize,
+    ) -> Result<bool, MerkleError> {
+        if index.0 >= size {
+            return Err(MerkleError::IndexOutOfBounds(index.0));
+        }
+        let depth = MerkleConfig::default().proof_length(size, index.0);
+        if proof.len() != depth {
+            return Err(MerkleError::WrongDepth {
+                expected: depth,
+                got: proof.len(),
+            });
+        }
+        Ok(proof_index(proof) == Some(index.0) && fold_proof(leaf_hash, proof) == root)
+    }
+
+    /// Like [`MerkleTree::verify_with_size`], but also rejects trees with fewer than `min_size`
+    /// leaves, for protocols where a trivial commitment, like a single leaf whose hash is the root,
+    /// must not prove membership.
+    ///
+    /// The size of the tree head is only a claim, so the proof must also have as many siblings as a
+    /// tree of that size gives the leaf, which [`MerkleTree::verify_with_size`] checks.
+    pub fn verify_with_min_size(
+        transaction: &H,
+        index: LeafIndex,
+        proof: &[SiblingHash],
+        tree_head: &TreeHead,
+        min_size: usize,
+    ) -> Result<bool, MerkleError> {
+        if tree_head.size < min_size {
+            return Ok(false);
+        }
+        Self::verify_with_size(transaction, index, proof, tree_head)
+    }
+
+    // Siblings come as (hex hash, is_left) pairs, the way web backends usually send them as JSON
+    pub fn verify_hex(
+        transaction: H,
+        proof: &[(String, bool)],
+        root_hex: &str,
+    ) -> Result<bool, MerkleError> {
+        let root = parse_hex(root_hex)?;
+        let proof = proof
+            .iter()
+            .map(|(hex, is_left)| {
+                let hash = parse_hex(hex)?;
+                Ok(if *is_left {
+                    SiblingHash::Left(hash)
+                } else {
+                    SiblingHash::Right(hash)
+                })
+            })
+            .collect::<Result<Vec<_>, MerkleError>>()?;
+
+        Ok(Self::fold_transaction(&transaction, &proof) == root)
+    }
+
+    /// Like [`MerkleTree::verify_hex`], for a root stored as a number in any radix from 2 to 36,
+    /// such as decimal, without a prefix.
+    pub fn verify_root_str(
+        transaction: H,
+        proof: &[SiblingHash],
+        root_str: &str,
+        radix: u32,
+    ) -> Result<bool, MerkleError> {
+        let bad_root = || MerkleError::BadRoot {
+            root: root_str.to_string(),
+            radix,
+        };
+        if !(2..=36).contains(&radix) {
+            return Err(bad_root());
+        }
+        let root = u64::from_str_radix(root_str, radix).map_err(|_| bad_root())?;
+        Ok(Self::fold_transaction(&transaction, proof) == Hash::from(root))
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl<H: LeafEncode> MerkleTree<H, CanonicalMerkleHasher> {
+    /// Builds a tree whose root follows a fixed spec, so implementations in other languages can
+    /// check they compute the same one:
+    ///
+    /// - A hash is the first 8 bytes of a SHA-256 digest, read as a big endian `u64`.
+    /// - A leaf hashes `0x00` followed by the bytes of its [`LeafEncode`], which are big endian
+    ///   for integers and UTF-8 for strings.
+    /// - A node hashes `0x01` followed by the hashes of its left and right sons, big endian.
+    /// - A lone node at the end of a level is paired with itself, and a single leaf is the root.
+    ///
+    /// The leaves `"a"`, `"b"` and `"c"` have the root `570e980096fcfce1`.
+    pub fn new_canonical(transactions: Vec<H>) -> Result<Self, MerkleError> {
+        Self::with_hasher(transactions, MerkleConfig::default())
+    }
+}
+
+impl<H: LeafEncode, M: MerkleHasher> MerkleTree<H, M> {
+    /// Tag identifying how this tree hashes leaves and nodes, carried by every [`Proof`].
+    pub const ALGORITHM: u8 = M::ALGORITHM;
+
+    /// The root of a tree without leaves, the hash of an empty byte string, so "no data yet" can
+    /// be committed to. A tree with a single empty leaf has the same root, so commitments tell
+    /// them apart by their size.
+    pub const EMPTY_ROOT: Hash = M::EMPTY_ROOT;
+
+    /// Like [`MerkleTree::with_config`], hashing with `M` instead of the default hasher.
+    pub fn with_hasher(transactions: Vec<H>, config: MerkleConfig) -> Result<Self, MerkleError> {
+        if config.arity < 2 {
+            return Err(MerkleError::BadArity(config.arity));
+        }
+        config.check_leaf_count(transactions.len())?;
+        Self::create_tree(transactions, config)
+    }
+
+    /// Builds the same tree hashed with another hasher, to migrate to it without collecting the
+    /// leaves again. Proofs of the new tree carry the new hasher's algorithm.
+    ///
+    /// Fails with [`MerkleError::NoLeafValues`] if the tree was built with
+    /// [`MerkleTree::from_leaf_hashes`] or pruned, since leaf hashes can't be hashed again.
+    pub fn rehash<M2: MerkleHasher>(&self) -> Result<MerkleTree<H, M2>, MerkleError>
+    where
+        H: Clone,
+    {
+        let leafs = self.leafs.as_ref().ok_or(MerkleError::NoLeafValues)?;
+        match &self.order {
+            Some(order) => {
+                MerkleTree::create_ordered_tree(leafs.clone(), order.clone(), self.config)
+            }
+            None => MerkleTree::with_hasher(leafs.clone(), self.config),
+        }
+    }
+
+    fn create_ordered_tree(
+        transactions: Vec<H>,
+        order: Vec<usize>,
+        config: MerkleConfig,
+    ) -> Result<Self, MerkleError> {
+        let mut placed = vec![false; transactions.len()];
+        let is_permutation = order.len() == transactions.len()
+            && order
+                .iter()
+                .all(|&index| index < placed.len() && !mem::replace(&mut placed[index], true));
+        if !is_permutation {
+            return Err(MerkleError::BadOrder);
+        }
+        let leaf_hashes = order
+            .iter()
+            .map(|&index| Self::configured_leaf_hash(&transactions[index], &config))
+            .collect();
+        Ok(Self {
+            leafs: Some(transactions),
+            order: Some(order),
+            ..Self::build_from_leaf_hashes(leaf_hashes, config)?
+        })
+    }
+
+    // Where the value at a tree position is in leafs
+    fn value_index(&self, position: usize) -> Option<usize> {
+        match &self.order {
+            Some(order) => order.get(position).copied(),
+            None => Some(position),
+        }
+    }
+
+    // Puts the values in tree order, for mutations that move leaves around
+    fn apply_order(&mut self) {
+        if let (Some(order), Some(leafs)) = (self.order.take(), &mut self.leafs) {
+            let mut slots: Vec<Option<H>> = mem::take(leafs).into_iter().map(Some).collect();
+            *leafs = order
+                .iter()
+                .map(|&index| slots[index].take().expect("the order is a permutation"))
+                .collect();
+        }
+    }
+
+    // Hashing the leaves is kept apart from building the tree, so trees read back from their
+    // hashes are built the same way as the ones made from values
+    fn create_tree(transactions: Vec<H>, config: MerkleConfig) -> Result<Self, MerkleError> {
+        let leaf_hashes = Self::get_hashes_of_transactions(&transactions, &config);
+        Ok(Self {
+            leafs: Some(transactions),
+            ..Self::build_from_leaf_hashes(leaf_hashes, config)?
+        })
+    }
+
+    fn build_from_leaf_hashes(
+        leaf_hashes: Vec<Hash>,
+        config: MerkleConfig,
+    ) -> Result<Self, MerkleError> {
+        let levels = Self::create_levels_of(&leaf_hashes, &config)?;
+        Ok(Self::from_levels(levels, config))
+    }
+
+    // The leaves are the first level, and the tree starts with no leaf values
+    fn from_levels(levels: Levels, config: MerkleConfig) -> Self {
+        Self {
+            leaf_hashes: levels[0].clone(),
+            levels: OnceLock::from(Ok(Arc::new(levels))),
+            leafs: None,
+            order: None,
+            rebuilds: AtomicUsize::new(0),
+            version: 0,
+            proof_cache: Mutex::default(),
+            leaf_index: OnceLock::new(),
+            root_listener: None,
+            staged: None,
+            config,
+            hasher: PhantomData,
+        }
+    }
+
+    fn create_levels_of(
+        leaf_hashes: &[Hash],
+        config: &MerkleConfig,
+    ) -> Result<Levels, MerkleError> {
+        if leaf_hashes.is_empty() {
+            return if config.allow_empty {
+                Ok(vec![Vec::new()])
+            } else {
+                Err(MerkleError::EmptyTransactions)
+            };
+        }
+
+        // A tree never has more levels than leaves, so going past that means the pairing is broken
+        let max_levels = leaf_hashes.len();
+
+        // Binary levels go to the hasher a level at a time, so a backend can hash their pairs its
+        // own way
+        if config.arity == 2 {
+            Self::create_levels_from_pairs(leaf_hashes.to_vec(), max_levels, config)
+        } else {
+            Self::create_levels(leaf_hashes.to_vec(), max_levels, config)
+        }
+    }
+
+    // The root over the leaf hashes of a non-empty iterator, which only collects the level above
+    // the leaves, hashing every group of leaves as soon as it is complete
+    fn root_of(
+        leaf_hashes: impl Iterator<Item = Hash>,
+        config: &MerkleConfig,
+    ) -> Result<Hash, MerkleError> {
+        let mut parents = Vec::new();
+        let mut group = Vec::with_capacity(config.arity);
+        for leaf_hash in leaf_hashes {
+            group.push(leaf_hash);
+            if group.len() == config.arity {
+                parents.push(config.create_parent::<M>(&group)?);
+                group.clear();
+            }
+        }
+        // A single leaf is the root itself, it has no parent
+        if parents.is_empty() && group.len() == 1 {
+            return Ok(group[0]);
+        }
+        if !group.is_empty() {
+            parents.push(config.create_parent::<M>(&group)?);
+        }
+        let levels = Self::create_levels_of(&parents, config)?;
+        Ok(levels[levels.len() - 1][0])
+    }
+
+    fn check_levels(levels: usize, max_levels: usize) -> Result<(), MerkleError> {
+        if levels > max_levels {
+            return Err(MerkleError::Internal(
+                "the tree has more levels than leaves",
+            ));
+        }
+        Ok(())
+    }
+
+    fn create_levels(
+        leaf_hashes: Vec<Hash>,
+        max_levels: usize,
+        config: &MerkleConfig,
+    ) -> Result<Levels, MerkleError> {
+        let mut levels = vec![leaf_hashes];
+        // We loop all the elements and construct the next level of the tree, we stop once there is only one element (the root)
+        while let Some(nodes) = levels.last().filter(|nodes| nodes.len() > 1) {
+            let parents = nodes
+                .chunks(config.arity)
+                .map(|group| config.create_parent::<M>(group))
+                .collect::<Result<_, _>>()?;
+            Self::check_levels(levels.len(), max_levels)?;
+            levels.push(parents);
+        }
+
+        Ok(levels)
+    }
+
+    // The exact pairs of a level go to MerkleHasher::hash_each_pair, and a lone last node gets its
+    // parent from the odd strategy
+    fn create_levels_from_pairs(
+        leaf_hashes: Vec<Hash>,
+        max_levels: usize,
+        config: &MerkleConfig,
+    ) -> Result<Levels, MerkleError> {
+        let mut levels = vec![leaf_hashes];
+        while let Some(nodes) = levels.last().filter(|nodes| nodes.len() > 1) {
+            let mut parents = M::hash_each_pair(nodes);
+            if !nodes.len().is_multiple_of(2) {
+                parents.push(config.create_parent::<M>(&nodes[nodes.len() - 1..])?);
+            }
+            Self::check_levels(levels.len(), max_levels)?;
+            levels.push(parents);
+        }
+
+        Ok(levels)
+    }
+
+    // Only the last node of every level can change, so this rehashes one path up to the root, and
+    // adds a new root on top when the leaves go past a power of the arity
+    fn push_leaf(
+        levels: &mut Levels,
+        leaf_hash: Hash,
+        config: &MerkleConfig,
+    ) -> Result<(), MerkleError> {
+        levels[0].push(leaf_hash);
+        let mut level = 0;
+        while levels[level].len() > 1 {
+            let nodes = &levels[level];
+            let parent_index = (nodes.len() - 1) / config.arity;
+            let parent = config.create_parent::<M>(&nodes[parent_index * config.arity..])?;
+
+            if level + 1 == levels.len() {
+                levels.push(Vec::new());
+            }
+            let parents = &mut levels[level + 1];
+            if parent_index < parents.len() {
+                parents[parent_index] = parent;
+            } else {
+                parents.push(parent);
+            }
+            level += 1;
+        }
+        Ok(())
+    }
+
+    fn salted_leaf_hash(transaction: &H, salt: Option<u64>) -> Hash {
+        Self::salted_bytes_hash(&transaction.encode(), salt)
+    }
+
+    fn salted_bytes_hash(bytes: &[u8], salt: Option<u64>) -> Hash {
+        let mut hasher = M::leaf_hasher();
+        if let Some(salt) = salt {
+            hasher.write(&salt.to_be_bytes());
+        }
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    // Every round after the first hashes the leaf hash before, with the leaf hasher
+    fn stretch_leaf_hash(mut leaf_hash: Hash, iterations: u32) -> Hash {
+        for _ in 1..iterations {
+            let mut hasher = M::leaf_hasher();
+            hasher.write(&leaf_hash.to_be_bytes());
+            leaf_hash = hasher.finish();
+        }
+        leaf_hash
+    }
+
+    fn configured_leaf_hash(transaction: &H, config: &MerkleConfig) -> Hash {
+        let leaf_hash = Self::salted_leaf_hash(transaction, config.salt);
+        Self::stretch_leaf_hash(leaf_hash, config.iterations)
+    }
+
+    fn hash_leaf(&self, transaction: &H) -> Hash {
+        Self::configured_leaf_hash(transaction, &self.config)
+    }
+
+    fn get_hashes_of_transactions(transactions: &[H], config: &MerkleConfig) -> Vec<Hash> {
+        transactions
+            .iter()
+            .map(|transaction| Self::configured_leaf_hash(transaction, config))
+            .collect()
+    }
+
+    fn fold_transaction<S: Borrow<SiblingHash>>(
+        transaction: &H,
+        proof: impl IntoIterator<Item = S>,
+    ) -> Hash {
+        fold_proof_with::<M, _>(Self::salted_leaf_hash(transaction, None), proof)
+    }
+
+    // Rebuilds the tree if a mutation left it dirty, OnceLock makes sure that only happens once
+    fn ensure_built(&self) -> Result<&Levels, MerkleError> {
+        self.built_levels().map(|levels| &**levels)
+    }
+
+    fn built_levels(&self) -> Result<&Arc<Levels>, MerkleError> {
+        self.levels
+            .get_or_init(|| {
+                self.rebuilds.fetch_add(1, Ordering::Relaxed);
+                Self::create_levels_of(&self.leaf_hashes, &self.config).map(Arc::new)
+            })
+            .as_ref()
+            .map_err(MerkleError::clone)
+    }
+
+    // Every mutation goes through here, so proofs issued before are stale: the version moves
+    // forward and cached proofs are dropped. Having &mut self, the cache doesn't need locking
+    fn mutated(&mut self) {
+        self.version += 1;
+        self.proof_cache
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        self.notify_root_change();
+    }
+
+    // With a listener the root can't wait for the next reader, it is rebuilt right away to be
+    // compared with the last one
+    fn notify_root_change(&mut self) {
+        if self.root_listener.is_none() || self.staged.is_some() {
+            return;
+        }
+        let root = self.try_root().ok();
+        if let Some(listener) = &mut self.root_listener {
+            if listener.root != root {
+                listener.root = root;
+                root.into_iter().for_each(&mut listener.callback);
+            }
+        }
+    }
+
+    fn invalidate(&mut self) {
+        self.levels = OnceLock::new();
+        self.leaf_index = OnceLock::new();
+        self.mutated();
+    }
+
+    // Nothing is a member of an empty tree, even a leaf whose hash happens to be the empty root
+    fn matches_root(&self, hash: Hash) -> bool {
+        !self.leaf_hashes.is_empty() && self.try_root() == Ok(hash)
+    }
+
+    // Every verifier bound to a tree goes through here, so strict mode holds whichever is used
+    fn proves<S: Borrow<SiblingHash>>(
+        &self,
+        hash: Hash,
+        proof: impl IntoIterator<Item = S>,
+    ) -> bool {
+        fold_strict::<M, _>(&self.config, hash, proof).is_some_and(|root| self.matches_root(root))
+    }
+
+    pub fn verify(&mut self, transaction: H, proof: Vec<SiblingHash>) -> bool {
+        self.verify_iter(transaction, proof)
+    }
+
+    // Folds the siblings as they come, so lazily produced proofs don't need to be collected first
+    pub fn verify_iter<I: IntoIterator<Item = SiblingHash>>(
+        &self,
+        transaction: H,
+        proof: I,
+    ) -> bool {
+        self.proves(self.hash_leaf(&transaction), proof)
+    }
+
+    /// Like [`MerkleTree::verify`], borrowing both the transaction and the proof, so the same proof
+    /// can be checked again, or against other trees, without cloning it.
+    pub fn verify_ref(&self, transaction: &H, proof: &[SiblingHash]) -> bool {
+        self.proves(self.hash_leaf(transaction), proof)
+    }
+
+    /// Whether `hash` is the root of this tree. An empty tree has no root, so nothing is.
+    pub fn is_root(&self, hash: Hash) -> bool {
+        self.matches_root(hash)
+    }
+
+    /// Like [`MerkleTree::verify_ref`], for the hash of any node instead of a leaf value: `proof`
+    /// goes from that node up to the root. The root itself is proven by an empty proof, so
+    /// `verify_hash(root, &[])` holds on every non-empty tree.
+    pub fn verify_hash(&self, hash: Hash, proof: &[SiblingHash]) -> bool {
+        self.proves(hash, proof)
+    }
+
+    /// Like [`MerkleTree::verify_ref`], for a leaf given as the bytes of its [`LeafEncode`], hashed
+    /// the way this tree hashes its leaves, with its salt and any prefix of its hasher.
+    pub fn verify_bytes(&self, leaf: &[u8], proof: &[SiblingHash]) -> bool {
+        let leaf_hash = Self::stretch_leaf_hash(
+            Self::salted_bytes_hash(leaf, self.config.salt),
+            self.config.iterations,
+        );
+        self.proves(leaf_hash, proof)
+    }
+
+    // Also returns the hashes computed, one for the leaf and then one per sibling folded, so
+    // callers can budget verification
+    pub fn verify_counted(&self, transaction: H, proof: &[SiblingHash]) -> (bool, usize) {
+        let mut hashes = 1;
+        let proves = self.proves(
+            self.hash_leaf(&transaction),
+            proof.iter().inspect(|_| hashes += 1),
+        );
+        (proves, hashes)
+    }
+
+    pub fn verify_compact(&self, transaction: &H, proof: &CompactProof) -> bool {
+        let leaf_hash = self.hash_leaf(transaction);
+        self.proves(leaf_hash, expand_proof_with::<M>(leaf_hash, proof))
+    }
+
+    // A proof made with another algorithm would just fail to fold to the root, so we reject it up
+    // front
+    fn verify_detached_proof(
+        transaction: &H,
+        proof: &Proof,
+        root: Hash,
+        iterations: u32,
+    ) -> Result<bool, MerkleError> {
+        if proof.algorithm != Self::ALGORITHM {
+            return Err(MerkleError::AlgorithmMismatch {
+                expected: Self::ALGORITHM,
+                found: proof.algorithm,
+            });
+        }
+
+        let leaf_hash =
+            Self::stretch_leaf_hash(Self::salted_leaf_hash(transaction, proof.salt), iterations);
+        Ok(fold_proof_with::<M, _>(leaf_hash, &proof.siblings) == root)
+    }
+
+    /// Like [`MerkleTree::verify_detached`] against this tree, but reports a proof issued before
+    /// the last mutation as [`MerkleError::StaleProof`] instead of just failing to verify.
+    pub fn verify_versioned(&self, transaction: &H, proof: &Proof) -> Result<bool, MerkleError> {
+        if proof.version != self.version {
+            return Err(MerkleError::StaleProof {
+                issued: proof.version,
+                current: self.version,
+            });
+        }
+        self.config.check_zero_siblings(&proof.siblings)?;
+        Self::verify_detached_proof(transaction, proof, self.try_root()?, self.config.iterations)
+    }
+
+    /// Like [`MerkleTree::verify_iter`], but rejects a proof that doesn't have exactly one sibling
+    /// per level. Otherwise a leaf whose encoding is a pair of node hashes hashes like their
+    /// parent, and a proof cut short at that parent folds to the root.
+    ///
+    /// Only trees whose config gives every level exactly one sibling are checked this way, proofs
+    /// of other trees are still checked by their root alone.
+    pub fn verify_checked(&self, transaction: &H, proof: &[SiblingHash]) -> bool {
+        if self.config.has_positional_proofs() && proof.len() != self.height() {
+            return false;
+        }
+        self.proves(self.hash_leaf(transaction), proof)
+    }
+
+    /// Checks `proof` proves `transaction` at `index`, rejecting an index past the last leaf with
+    /// [`MerkleError::IndexOutOfBounds`], and a proof longer or shorter than the leaf is deep with
+    /// [`MerkleError::WrongDepth`], before folding anything. Leaves have different depths when
+    /// lone nodes are promoted.
+    pub fn verify_at(
+        &self,
+        transaction: &H,
+        index: LeafIndex,
+        proof: &[SiblingHash],
+    ) -> Result<bool, MerkleError> {
+        if index.0 >= self.leaf_hashes.len() {
+            return Err(MerkleError::IndexOutOfBounds(index.0));
+        }
+        self.config.check_zero_siblings(proof)?;
+        let depth = self.config.proof_length(self.leaf_hashes.len(), index.0);
+        if proof.len() != depth {
+            return Err(MerkleError::WrongDepth {
+                expected: depth,
+                got: proof.len(),
+            });
+        }
+        // Only binary proofs say where their leaf is, others are checked by their root alone
+        let at_index = !self.config.has_positional_proofs() || proof_index(proof) == Some(index.0);
+        Ok(at_index && self.proves(self.hash_leaf(transaction), proof))
+    }
+
+    // Debugging aid: a proof handed over root-to-leaf instead of leaf-to-root is a common
+    // integration mistake
+    pub fn verify_diagnostic(&self, transaction: H, proof: &[SiblingHash]) -> ProofDiagnostic {
+        let leaf_hash = self.hash_leaf(&transaction);
+        if self.proves(leaf_hash, proof) {
+            ProofDiagnostic::Valid
+        } else if self.proves(leaf_hash, proof.iter().rev()) {
+            ProofDiagnostic::Reversed
+        } else {
+            ProofDiagnostic::Invalid
+        }
+    }
+
+    pub fn get_proof(&mut self, transaction: H) -> Vec<SiblingHash> {
+        let mut proof = Vec::new();
+        self.get_proof_into(&transaction, &mut proof);
+        proof
+    }
+
+    // A transaction that is not in the tree gets an empty proof, which only verifies a lone leaf
+    pub fn try_get_proof(&self, transaction: &H) -> Result<Vec<SiblingHash>, MerkleError> {
+        let mut proof = Vec::new();
+        let levels = self.ensure_built()?;
+        if let Some(index) = self.position_of(transaction) {
+            self.write_proof(levels, index, &mut proof);
+        }
+        Ok(proof)
+    }
+
+    // Reuses the caller's buffer so hot loops don't allocate a new proof every time
+    pub fn get_proof_into(&self, transaction: &H, buf: &mut Vec<SiblingHash>) {
+        buf.clear();
+        if let (Ok(levels), Some(index)) = (self.ensure_built(), self.position_of(transaction)) {
+            self.write_proof(levels, index, buf);
+        }
+    }
+
+    /// Like [`MerkleTree::get_proof`], but keeps the proof to answer the same transaction again
+    /// until the tree is mutated. Takes `&self`, so it can be shared across threads in an `Arc`.
+    ///
+    /// The cache sits behind a single `Mutex`, held only to look a proof up and to store it, never
+    /// while it is computed. Threads asking at once only contend for those short sections, and may
+    /// compute the same missing proof twice, which is harmless as it is always the same one.
+    pub fn get_proof_cached(&self, transaction: &H) -> Vec<SiblingHash> {
+        let leaf_hash = self.hash_leaf(transaction);
+        if let Some(proof) = self.lock_proof_cache().get(&leaf_hash) {
+            return proof.clone();
+        }
+
+        let mut proof = Vec::new();
+        self.get_proof_into(transaction, &mut proof);
+        self.lock_proof_cache().insert(leaf_hash, proof.clone());
+        proof
+    }
+
+    // A panic can't leave the cache half written, so a poisoned lock is still fine to use
+    fn lock_proof_cache(&self) -> MutexGuard<'_, HashMap<Hash, Vec<SiblingHash>>> {
+        self.proof_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Either way a repeated leaf is found at its first index. Only the leaf level is searched, so a
+    // value whose leaf hash is the hash of an internal node, see verify_checked, isn't found
+    fn position_of(&self, transaction: &H) -> Option<usize> {
+        let leaf_hash = self.hash_leaf(transaction);
+        if self.config.index_leaves {
+            return self.leaf_index().get(&leaf_hash).copied();
+        }
+        self.leaf_hashes.iter().position(|hash| *hash == leaf_hash)
+    }
+
+    fn leaf_index(&self) -> &HashMap<Hash, usize> {
+        self.leaf_index.get_or_init(|| {
+            let mut leaf_index = HashMap::with_capacity(self.leaf_hashes.len());
+            for (index, leaf_hash) in self.leaf_hashes.iter().enumerate() {
+                leaf_index.entry(*leaf_hash).or_insert(index);
+            }
+            leaf_index
+        })
+    }
+
+    fn write_proof(&self, levels: &Levels, index: usize, proof: &mut Vec<SiblingHash>) {
+        write_proof::<M>(levels, &self.config, index, proof);
+    }
+
+    /// Freezes the current nodes, so proofs against the current root can still be served after
+    /// the tree changes, as an append-only log does for every checkpoint it published. Cheap to
+    /// take: the nodes are shared, and only copied by the next [`MerkleTree::add`].
+    pub fn snapshot(&self) -> Result<RootSnapshot<H, M>, MerkleError> {
+        Ok(RootSnapshot {
+            levels: Arc::clone(self.built_levels()?),
+            config: self.config,
+            leaf: PhantomData,
+        })
+    }
+
+    /// The hash of the lowest node covering both leaves, found by going up from both until they
+    /// meet, which is the leaf itself when `i` and `j` are the same. `None` if either is past the
+    /// last leaf.
+    pub fn common_ancestor(&self, i: LeafIndex, j: LeafIndex) -> Option<Hash> {
+        let levels = self.ensure_built().ok()?;
+        let (mut i, mut j) = (i.0, j.0);
+        if i.max(j) >= self.leaf_hashes.len() {
+            return None;
+        }
+        let mut level = 0;
+        while i != j {
+            i /= self.config.arity;
+            j /= self.config.arity;
+            level += 1;
+        }
+        Some(levels[level][i])
+    }
+
+    /// Checks every node of the built tree against its sons: each level has one node per group of
+    /// sons below it, up to a single root, and each node's hash is the one [`MerkleTree::add`] or
+    /// a rebuild would compute from them. Unlike verifying proofs, this covers every node once.
+    pub fn validate_structure(&self) -> Result<(), StructuralError> {
+        let levels = self.ensure_built().map_err(StructuralError::Build)?;
+        // The leaves level is checked against the leaf hashes, then every other against its sons
+        for level in 0..levels.len() {
+            let nodes = &levels[level];
+            let computed: Vec<Hash> = if level == 0 {
+                self.leaf_hashes.clone()
+            } else {
+                levels[level - 1]
+                    .chunks(self.config.arity)
+                    .map(|group| self.config.create_parent::<M>(group))
+                    .collect::<Result<_, _>>()
+                    .map_err(StructuralError::Build)?
+            };
+            if nodes.len() != computed.len() {
+                return Err(StructuralError::LevelLength {
+                    level,
+                    expected: computed.len(),
+                    found: nodes.len(),
+                });
+            }
+            if let Some(index) = nodes.iter().zip(&computed).position(|(a, b)| a != b) {
+                return Err(StructuralError::NodeMismatch {
+                    level,
+                    index,
+                    stored: nodes[index],
+                    computed: computed[index],
+                });
+            }
+        }
+        let top = levels[levels.len() - 1].len();
+        if top > 1 {
+            return Err(StructuralError::LevelLength {
+                level: levels.len() - 1,
+                expected: 1,
+                found: top,
+            });
+        }
+        Ok(())
+    }
+
+    /// The number of levels above the leaves.
+    pub fn height(&self) -> usize {
+        self.ensure_built().map_or(0, |levels| levels.len() - 1)
+    }
+
+    pub fn proof_for(&self, index: LeafIndex) -> Option<Vec<SiblingHash>> {
+        if index.0 >= self.leaf_hashes.len() {
+            return None;
+        }
+
+        let mut proof = Vec::new();
+        self.write_proof(self.ensure_built().ok()?, index.0, &mut proof);
+        Some(proof)
+    }
+
+    /// The proof of the leaf at `index` along with its path from the root as an integer, for
+    /// binary trie addressing: bit `i` is set when the `i`-th step down from the root goes right.
+    /// `None` for configs whose proofs don't say where their leaf is, see
+    /// [`MerkleTree::verify_at`].
+    pub fn get_proof_with_path(&self, index: LeafIndex) -> Option<(u64, Vec<SiblingHash>)> {
+        if !self.config.has_positional_proofs() {
+            return None;
+        }
+        let proof = self.proof_for(index)?;
+        // The index reads the same sides from the leaf up, so the path is its bits reversed
+        let depth = proof.len();
+        let path = (0..depth).fold(0, |path, step| {
+            path | (index.0 as u64 >> (depth - 1 - step) & 1) << step
+        });
+        Some((path, proof))
+    }
+
+    /// Whether `proof` is exactly the path of the leaf at `index`, compared sibling by sibling
+    /// against the tree's own nodes. The proof isn't folded, and one tampered with low in the tree
+    /// is rejected at the first sibling that differs, which makes this cheaper than verifying it.
+    pub fn proof_matches_path(&self, index: LeafIndex, proof: &[SiblingHash]) -> bool {
+        self.mismatched_level(index, proof).is_none()
+    }
+
+    // The first level where the proof leaves the path of the leaf, or where one of them ends
+    // before the other
+    fn mismatched_level(&self, index: LeafIndex, proof: &[SiblingHash]) -> Option<usize> {
+        let Some(path) = self.proof_for(index) else {
+            return Some(0);
+        };
+        path.iter()
+            .zip(proof)
+            .position(|(expected, given)| expected != given)
+            .or_else(|| (path.len() != proof.len()).then(|| path.len().min(proof.len())))
+    }
+
+    /// Proves the leaves at `indices` as one [`QuorumProof`], with the siblings they share stored
+    /// once. Returns `None` if any of them doesn't exist.
+    ///
+    /// The proof is canonical, so the same set of leaves always gives the same one whatever the
+    /// order or repeats in `indices`: the leaves are sorted ascending without repeats, and the
+    /// siblings are stored in the order they are first met going through their proofs, each from
+    /// the leaf up.
+    pub fn quorum_proof(&self, indices: &[LeafIndex]) -> Option<QuorumProof> {
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        let requests: Vec<(usize, LeafIndex)> = indices.iter().map(|index| (0, *index)).collect();
+        let batch = ProofBatcher::new(vec![self]).batch(&requests)?;
+        let leaves = indices
+            .iter()
+            .map(|index| (*index, self.leaf_hashes[index.0]))
+            .collect();
+        Some(QuorumProof { leaves, batch })
+    }
+
+    /// Whether at least `k` distinct leaves of the quorum proof verify at their index, so a
+    /// leaf claimed twice only counts once.
+    pub fn verify_quorum(&self, proof: &QuorumProof, k: usize) -> bool {
+        let mut verified = HashSet::new();
+        for (request, (index, leaf_hash)) in proof.leaves.iter().enumerate() {
+            let Some(siblings) = proof.batch.proof(request) else {
+                continue;
+            };
+            let at_index =
+                !self.config.has_positional_proofs() || proof_index(&siblings) == Some(index.0);
+            if at_index && self.proves(*leaf_hash, &siblings) {
+                verified.insert(*index);
+            }
+        }
+        verified.len() >= k
+    }
+
+    /// The proofs of the leaves from `start` up to `end`, excluded, each written only when the
+    /// iterator reaches it, so a window of a huge tree can be streamed out without holding every
+    /// proof. `end` is clamped to the number of leaves, and a tree that can't be built gives none.
+    pub fn proofs_in_range(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> impl Iterator<Item = (LeafIndex, Vec<SiblingHash>)> + '_ {
+        let end = end.min(self.leaf_hashes.len());
+        self.ensure_built().into_iter().flat_map(move |levels| {
+            (start..end).map(move |index| {
+                let mut proof = Vec::new();
+                self.write_proof(levels, index, &mut proof);
+                (LeafIndex(index), proof)
+            })
+        })
+    }
+
+    /// The proof of every leaf, serialized as one [`ProofBundle`] along with the root, so they can
+    /// be handed out as a single file.
+    pub fn export_all_proofs(&self) -> Vec<u8> {
+        let proofs: Vec<Vec<SiblingHash>> = match self.ensure_built() {
+            Ok(levels) => (0..self.leaf_hashes.len())
+                .map(|index| {
+                    let mut proof = Vec::new();
+                    self.write_proof(levels, index, &mut proof);
+                    proof
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        let root = self.try_root().unwrap_or(Self::EMPTY_ROOT);
+        ProofBundle::new(M::ALGORITHM, self.config.salt, root, &proofs).into_bytes()
+    }
+
+    /// The length of the proof of every leaf, in order.
+    ///
+    /// Lone nodes duplicated to get a pair still add a sibling, so with the default config every
+    /// proof has [`MerkleTree::height`] siblings even when the number of leaves is odd. Lengths
+    /// only vary with configs that promote lone nodes or group more than two sons.
+    pub fn proof_lengths(&self) -> Vec<usize> {
+        let Ok(levels) = self.ensure_built() else {
+            return Vec::new();
+        };
+        let mut proof = Vec::new();
+        (0..self.leaf_hashes.len())
+            .map(|index| {
+                proof.clear();
+                self.write_proof(levels, index, &mut proof);
+                proof.len()
+            })
+            .collect()
+    }
+
+    pub fn witness(&self, index: LeafIndex) -> Option<Witness<H>>
+    where
+        H: Clone,
+    {
+        Some(Witness {
+            leaf: self.get_leaf(index)?.clone(),
+            index,
+            proof: self.proof_for(index)?,
+        })
+    }
+
+    /// Checks the witness like [`MerkleTree::verify_at`], so its proof has to lead to its index
+    /// as well as to the root, and an index past the last leaf just fails.
+    pub fn verify_witness(&self, witness: &Witness<H>) -> bool {
+        self.verify_at(&witness.leaf, witness.index, &witness.proof)
+            .unwrap_or(false)
+    }
+
+    /// The inclusion proofs of the leaves at `i` and `j`, which [`MerkleTree::verify_equality`]
+    /// only accepts if both leaves have the same leaf hash. `None` if either is past the last leaf.
+    pub fn equality_proof(&self, i: LeafIndex, j: LeafIndex) -> Option<EqualityProof> {
+        Some(EqualityProof {
+            first: (i, self.expected_leaf_for(i)?, self.proof_for(i)?),
+            second: (j, self.expected_leaf_for(j)?, self.proof_for(j)?),
+        })
+    }
+
+    /// Checks both leaf hashes are the same and both proofs lead to the root, and to their index
+    /// like [`MerkleTree::verify_at`].
+    pub fn verify_equality(&self, proof: &EqualityProof) -> bool {
+        let proves = |(index, leaf_hash, siblings): &(LeafIndex, Hash, Vec<SiblingHash>)| {
+            index.0 < self.leaf_hashes.len()
+                && siblings.len() == self.config.proof_length(self.leaf_hashes.len(), index.0)
+                && (!self.config.has_positional_proofs() || proof_index(siblings) == Some(index.0))
+                && self.proves(*leaf_hash, siblings)
+        };
+        proof.first.1 == proof.second.1 && proves(&proof.first) && proves(&proof.second)
+    }
+
+    /// Builds the proof of the leaf at `index` once, for a leaf that is verified often.
+    pub fn prepare_verifier(&self, index: LeafIndex) -> Option<PreparedVerifier<'_, H, M>> {
+        Some(PreparedVerifier {
+            merkle_tree: self,
+            siblings: self.proof_for(index)?,
+            root: self.try_root().ok()?,
+        })
+    }
+
+    pub fn detached_proof(&self, index: LeafIndex) -> Option<Proof> {
+        self.proof_for(index).map(|siblings| Proof {
+            algorithm: Self::ALGORITHM,
+            version: self.version,
+            salt: self.config.salt,
+            siblings,
+        })
+    }
+
+    /// The node hashes in breadth first order, so the sons of the node at `i` are at `2i + 1` and
+    /// `2i + 2`, or `ki + 1` up to `ki + k` with an arity of `k`. Empty if the leaves were left
+    /// empty through [`MerkleTree::leaves_mut`].
+    pub fn to_array(&self) -> Vec<Hash> {
+        let Ok(levels) = self.ensure_built() else {
+            return Vec::new();
+        };
+        if self.leaf_hashes.is_empty() {
+            return Vec::new();
+        }
+
+        // Duplicated nodes come with their whole sub-tree, so past the end of a level every row of
+        // the array repeats the last node, and its sons are the sons of that node. Any other
+        // padding is not a node, and is left as the default hash along with everything below it
+        let arity = self.config.arity;
+        let duplicates = self.config.odd_strategy == OddStrategy::Duplicate;
+        let mut hashes = Vec::new();
+        let mut positions = vec![Some(0)];
+        for (depth, nodes) in levels.iter().rev().enumerate() {
+            hashes.extend(
+                positions
+                    .iter()
+                    .map(|position| position.map_or(Hash::default(), |position| nodes[position])),
+            );
+            if let Some(sons) = levels
+                .len()
+                .checked_sub(depth + 2)
+                .map(|level| &levels[level])
+            {
+                positions = positions
+                    .iter()
+                    .flat_map(|position| {
+                        (0..arity).map(move |son| position.map(|position| arity * position + son))
+                    })
+                    .map(|position| match position {
+                        Some(position) if position < sons.len() => Some(position),
+                        Some(_) if duplicates => Some(sons.len() - 1),
+                        _ => None,
+                    })
+                    .collect();
+            }
+        }
+        hashes
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn salt(&self) -> Option<u64> {
+        self.config.salt
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the leaves were left empty through [`MerkleTree::leaves_mut`], see
+    /// [`MerkleTree::try_root`].
+    pub fn root(&self) -> Hash {
+        self.try_root().unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    pub fn try_root(&self) -> Result<Hash, MerkleError> {
+        self.ensure_built().map(|levels| {
+            levels[levels.len() - 1]
+                .first()
+                .copied()
+                .unwrap_or(Self::EMPTY_ROOT)
+        })
+    }
+
+    /// The root the tree would have after [`MerkleTree::add`] of `transaction`, for dry runs,
+    /// without changing the tree. It fails where `add` would, like with
+    /// [`MerkleError::TooManyLeaves`], so it never gives a root `add` can't reach. The leaf hashes
+    /// are hashed into their parents as they are read, without being copied.
+    pub fn root_if_added(&self, transaction: &H) -> Result<Hash, MerkleError> {
+        self.config.check_leaf_count(self.leaf_hashes.len() + 1)?;
+        let leaf_hashes = self
+            .leaf_hashes
+            .iter()
+            .copied()
+            .chain(iter::once(self.hash_leaf(transaction)));
+        Self::root_of(leaf_hashes, &self.config)
+    }
+
+    /// The root the tree would have with its leaves sorted by value, without changing the tree.
+    /// Trees whose leaves are a permutation of each other share it, though their roots differ, so
+    /// it commits to the leaves regardless of their order. It is the root
+    /// [`MerkleTree::verify_set`] checks, and the one [`MerkleTree::from_sorted_unchecked`] and
+    /// [`MerkleTree::insert_sorted`] keep, for trees with the same config.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree has no leaf values to sort, see [`MerkleTree::try_sorted_root`].
+    pub fn sorted_root(&self) -> Hash
+    where
+        H: Ord,
+    {
+        self.try_sorted_root()
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Like [`MerkleTree::sorted_root`], but fails with [`MerkleError::NoLeafValues`] if the tree
+    /// has no leaf values to sort, and like [`MerkleTree::try_root`] if the leaves were left empty
+    /// through [`MerkleTree::leaves_mut`].
+    pub fn try_sorted_root(&self) -> Result<Hash, MerkleError>
+    where
+        H: Ord,
+    {
+        if self.leafs.is_none() {
+            return Err(MerkleError::NoLeafValues);
+        }
+        let mut positions: Vec<usize> = (0..self.leaf_hashes.len()).collect();
+        positions.sort_by_key(|&position| self.get_leaf(LeafIndex(position)));
+        let leaf_hashes: Vec<Hash> = positions
+            .iter()
+            .map(|&position| self.leaf_hashes[position])
+            .collect();
+        let levels = Self::create_levels_of(&leaf_hashes, &self.config)?;
+        Ok(levels[levels.len() - 1]
+            .first()
+            .copied()
+            .unwrap_or(Self::EMPTY_ROOT))
+    }
+
+    pub fn tree_head(&self) -> TreeHead {
+        TreeHead::new(self.root(), self.leaf_hashes.len())
+    }
+
+    pub fn tree_head_at(&self, timestamp: u64) -> TreeHead {
+        TreeHead::at(self.root(), self.leaf_hashes.len(), timestamp)
+    }
+
+    pub fn commitment(&self) -> DatasetCommitment {
+        DatasetCommitment {
+            root: self.root(),
+            size: self.leaf_hashes.len(),
+            algorithm: Self::ALGORITHM,
+        }
+    }
+
+    pub fn get_leaf(&self, index: LeafIndex) -> Option<&H> {
+        self.leafs.as_ref()?.get(self.value_index(index.0)?)
+    }
+
+    /// Only rehashes the path from the new leaf to the root, unless the tree was already waiting
+    /// to be rebuilt, in which case the leaf is just picked up by that rebuild. Returns the index
+    /// of the new leaf, to get its proof right away.
+    pub fn add(&mut self, transaction: H) -> Result<LeafIndex, MerkleError> {
+        self.config.check_leaf_count(self.leaf_hashes.len() + 1)?;
+        let leaf_hash = self.hash_leaf(&transaction);
+        if let Some(leafs) = &mut self.leafs {
+            if let Some(order) = &mut self.order {
+                order.push(leafs.len());
+            }
+            leafs.push(transaction);
+        }
+        Ok(self.add_leaf_hash(leaf_hash))
+    }
+
+    /// Adds a leaf streamed from `reader` in chunks, for blobs too large to hold in memory. Its
+    /// leaf hash is the one of a `Vec<u8>` leaf with the same bytes, so it can be proven as one.
+    ///
+    /// Fails with [`MerkleError::NoLeafValues`] if the tree keeps leaf values, as there is no
+    /// value for the streamed leaf. Trees built with [`MerkleTree::from_leaf_hashes`] or pruned
+    /// with [`MerkleTree::prune_leaves`] can be streamed into.
+    pub fn add_reader<R: Read>(&mut self, mut reader: R) -> Result<LeafIndex, MerkleError> {
+        if self.leafs.is_some() {
+            return Err(MerkleError::NoLeafValues);
+        }
+        self.config.check_leaf_count(self.leaf_hashes.len() + 1)?;
+        let mut hasher = M::leaf_hasher();
+        if let Some(salt) = self.config.salt {
+            hasher.write(&salt.to_be_bytes());
+        }
+        // SipHash buffers what it is given, so writing in chunks hashes like a single write
+        let mut chunk = [0; 8192];
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => hasher.write(&chunk[..read]),
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(MerkleError::Read(error.kind())),
+            }
+        }
+        let leaf_hash = Self::stretch_leaf_hash(hasher.finish(), self.config.iterations);
+        Ok(self.add_leaf_hash(leaf_hash))
+    }
+
+    fn add_leaf_hash(&mut self, leaf_hash: Hash) -> LeafIndex {
+        let index = LeafIndex(self.leaf_hashes.len());
+        if let Some(leaf_index) = self.leaf_index.get_mut() {
+            leaf_index
+                .entry(leaf_hash)
+                .or_insert(self.leaf_hashes.len());
+        }
+        self.leaf_hashes.push(leaf_hash);
+        // A failed push leaves the levels half updated, so they are rebuilt, which reports the
+        // error when the tree is read
+        let pushed = match self.levels.get_mut() {
+            Some(Ok(levels)) if self.staged.is_none() => {
+                Self::push_leaf(Arc::make_mut(levels), leaf_hash, &self.config).is_ok()
+            }
+            _ => false,
+        };
+        if pushed {
+            self.mutated();
+        } else {
+            self.invalidate();
+        }
+        index
+    }
+
+    /// The proofs that [`MerkleTree::update`] at `index` would change, so clients can refresh only
+    /// those.
+    ///
+    /// An update changes every node on the path of the leaf, and every other leaf's path meets it
+    /// somewhere below the root, so the proof of every other leaf gets a new sibling where the two
+    /// paths meet, whatever the arity. The updated leaf keeps its proof, unless it is a lone node
+    /// duplicated to get a pair, as it is then its own sibling.
+    ///
+    /// Other mutations are not covered: [`MerkleTree::add`] changes the last path and adds a
+    /// sibling to every proof when the tree grows a level, while [`MerkleTree::insert_sorted`] and
+    /// [`MerkleTree::leaves_mut`] move leaves around, which can change any proof. Whatever the
+    /// mutation, the version moves, so [`MerkleTree::verify_versioned`] rejects every earlier
+    /// proof.
+    pub fn mutation_impact(&self, index: LeafIndex) -> Result<ProofImpact, MerkleError> {
+        let levels = self.ensure_built()?;
+        if index.0 >= self.leaf_hashes.len() {
+            return Err(MerkleError::IndexOutOfBounds(index.0));
+        }
+
+        // A leaf's proof changes where a group it takes siblings from holds the updated path
+        let arity = self.config.arity;
+        let duplicates = self.config.odd_strategy == OddStrategy::Duplicate;
+        let invalidated = (0..self.leaf_hashes.len())
+            .filter(|&leaf| {
+                let (mut changed, mut node) = (index.0, leaf);
+                levels[..levels.len() - 1].iter().any(|nodes| {
+                    let group_start = node - node % arity;
+                    let group_end = nodes.len().min(group_start + arity);
+                    let padded_with_itself =
+                        duplicates && group_end - group_start < arity && node == group_end - 1;
+                    let impacted = (group_start..group_end).contains(&changed)
+                        && (changed != node || padded_with_itself);
+                    changed /= arity;
+                    node /= arity;
+                    impacted
+                })
+            })
+            .map(LeafIndex)
+            .collect();
+        Ok(ProofImpact { invalidated })
+    }
+
+    pub fn update(&mut self, index: LeafIndex, transaction: H) -> Result<(), MerkleError> {
+        let new_hash = self.hash_leaf(&transaction);
+        let leaf_hash = self
+            .leaf_hashes
+            .get_mut(index.0)
+            .ok_or(MerkleError::IndexOutOfBounds(index.0))?;
+        *leaf_hash = new_hash;
+        if let Some(value_index) = self.value_index(index.0) {
+            if let Some(leafs) = &mut self.leafs {
+                leafs[value_index] = transaction;
+            }
+        }
+        self.invalidate();
+        Ok(())
+    }
+
+    /// Removes every leaf equal to `transaction`, rebuilding the tree once, and returns how many
+    /// were removed. Trees without leaf values match them by their leaf hash instead.
+    ///
+    /// Fails with [`MerkleError::EmptyTransactions`], leaving the tree as it was, if no leaf would
+    /// be left in a tree that doesn't allow it.
+    pub fn remove_all(&mut self, transaction: &H) -> Result<usize, MerkleError>
+    where
+        H: PartialEq,
+    {
+        let leaf_hash = self.hash_leaf(transaction);
+        // By tree position
+        let keep: Vec<bool> = match &self.leafs {
+            Some(_) => (0..self.leaf_hashes.len())
+                .map(|position| self.get_leaf(LeafIndex(position)) != Some(transaction))
+                .collect(),
+            None => self
+                .leaf_hashes
+                .iter()
+                .map(|hash| *hash != leaf_hash)
+                .collect(),
+        };
+        let removed = keep.iter().filter(|keep| !**keep).count();
+        if removed == 0 {
+            return Ok(0);
+        }
+        if removed == keep.len() && !self.config.allow_empty {
+            return Err(MerkleError::EmptyTransactions);
+        }
+
+        let mut kept = keep.iter().copied();
+        self.leaf_hashes.retain(|_| kept.next() == Some(true));
+        // With an order, the values kept are renumbered by how many before them were removed
+        let keep_values = match &mut self.order {
+            Some(order) => {
+                let mut keep_values = vec![true; order.len()];
+                for (position, &index) in order.iter().enumerate() {
+                    keep_values[index] = keep[position];
+                }
+                let mut kept = keep.iter().copied();
+                order.retain(|_| kept.next() == Some(true));
+                let removed_before: Vec<usize> = keep_values
+                    .iter()
+                    .scan(0, |removed, keep| {
+                        let before = *removed;
+                        *removed += usize::from(!keep);
+                        Some(before)
+                    })
+                    .collect();
+                order
+                    .iter_mut()
+                    .for_each(|index| *index -= removed_before[*index]);
+                keep_values
+            }
+            None => keep,
+        };
+        if let Some(leafs) = &mut self.leafs {
+            let mut kept = keep_values.iter().copied();
+            leafs.retain(|_| kept.next() == Some(true));
+        }
+        self.invalidate();
+        Ok(removed)
+    }
+
+    /// Registers `f` to be called with the new root after every mutation that changes it, replacing
+    /// any callback registered before.
+    ///
+    /// Mutations then rebuild the tree as they happen instead of on the next read, and leaves left
+    /// empty through [`MerkleTree::leaves_mut`] don't call it, as there is no root to report.
+    pub fn on_root_change<F: FnMut(Hash) + Send + Sync + 'static>(&mut self, f: F) {
+        self.root_listener = Some(RootListener {
+            callback: Box::new(f),
+            root: self.try_root().ok(),
+        });
+    }
+
+    pub fn leaf_hashes(&self) -> &[Hash] {
+        &self.leaf_hashes
+    }
+
+    /// The leaf hash stored at `index`, salted if the tree is, for a prover to cross-check the
+    /// leaf hash it claims before folding it with [`fold_proof`]. `None` past the last leaf.
+    pub fn expected_leaf_for(&self, index: LeafIndex) -> Option<Hash> {
+        self.leaf_hashes.get(index.0).copied()
+    }
+
+    pub fn leaves(&self) -> Option<&[H]> {
+        self.leafs.as_deref()
+    }
+
+    /// Pairs of leaf indices whose leaf hashes collide even though their values differ, which
+    /// makes proofs by value ambiguous. Hashes are only 64 bits, so this can happen on large
+    /// inputs. Empty if the tree has no leaf values.
+    pub fn detect_collisions(&self) -> Vec<(usize, usize)>
+    where
+        H: PartialEq,
+    {
+        if self.leafs.is_none() {
+            return Vec::new();
+        }
+        let mut indices_by_hash: HashMap<Hash, Vec<usize>> = HashMap::new();
+        for (index, leaf_hash) in self.leaf_hashes.iter().enumerate() {
+            indices_by_hash.entry(*leaf_hash).or_default().push(index);
+        }
+
+        let mut collisions: Vec<_> = indices_by_hash
+            .values()
+            .flat_map(|indices| {
+                indices
+                    .iter()
+                    .enumerate()
+                    .flat_map(move |(position, &first)| {
+                        indices[position + 1..]
+                            .iter()
+                            .map(move |&second| (first, second))
+                    })
+            })
+            .filter(|&(first, second)| {
+                self.get_leaf(LeafIndex(first)) != self.get_leaf(LeafIndex(second))
+            })
+            .collect();
+        collisions.sort_unstable();
+        collisions
+    }
+
+    /// Drops the leaf values, for verifiers that only serve proofs and don't need them.
+    ///
+    /// Proofs only need the node hashes, so [`MerkleTree::proof_for`], [`MerkleTree::root`] and
+    /// even proofs by value, which look the leaf up by its hash, keep working. What is lost is
+    /// everything that reads the values back: [`MerkleTree::get_leaf`] and
+    /// [`MerkleTree::leaves`] find nothing, and the tree can't be sorted or edited through
+    /// [`MerkleTree::leaves_mut`] anymore, just like a tree built with
+    /// [`MerkleTree::from_leaf_hashes`].
+    pub fn prune_leaves(&mut self) {
+        self.leafs = None;
+        self.order = None;
+    }
+
+    /// Inserts the transaction where it keeps the leaves sorted and returns its index, so
+    /// [`MerkleTree::proof_of_absence`] can be used without the caller managing the order.
+    ///
+    /// Fails with [`MerkleError::NoLeafValues`] if the tree was built with
+    /// [`MerkleTree::from_leaf_hashes`] or pruned, as there are no values to sort.
+    pub fn insert_sorted(&mut self, transaction: H) -> Result<LeafIndex, MerkleError>
+    where
+        H: Ord,
+    {
+        self.config.check_leaf_count(self.leaf_hashes.len() + 1)?;
+        let leaf_hash = self.hash_leaf(&transaction);
+        self.apply_order();
+        let leafs = self.leafs.as_mut().ok_or(MerkleError::NoLeafValues)?;
+        let index = leafs.partition_point(|leaf| leaf <= &transaction);
+        self.leaf_hashes.insert(index, leaf_hash);
+        leafs.insert(index, transaction);
+        self.invalidate();
+        Ok(LeafIndex(index))
+    }
+
+    /// Whether `other` is this tree with leaves appended, that is, whether this tree's leaves are
+    /// the first of `other`. Trees without leaf values are compared by their leaf hashes.
+    pub fn is_prefix_of(&self, other: &MerkleTree<H, M>) -> bool
+    where
+        H: PartialEq,
+    {
+        match (&self.leafs, &other.leafs) {
+            (Some(leafs), Some(other_leafs)) if self.order.is_none() && other.order.is_none() => {
+                other_leafs.starts_with(leafs)
+            }
+            _ => other.leaf_hashes.starts_with(&self.leaf_hashes),
+        }
+    }
+
+    /// Merges the sorted leaves of both trees in one pass and rebuilds, keeping them sorted for
+    /// [`MerkleTree::proof_of_absence`]. The merged tree has the config of `self`.
+    ///
+    /// Fails with [`MerkleError::NoLeafValues`] if either tree was built with
+    /// [`MerkleTree::from_leaf_hashes`] or pruned, as there are no values to merge.
+    pub fn merge_sorted(
+        mut self,
+        mut other: MerkleTree<H, M>,
+    ) -> Result<MerkleTree<H, M>, MerkleError>
+    where
+        H: Ord,
+    {
+        self.apply_order();
+        other.apply_order();
+        let (Some(left), Some(right)) = (self.leafs, other.leafs) else {
+            return Err(MerkleError::NoLeafValues);
+        };
+        let mut left = left.into_iter().peekable();
+        let mut right = right.into_iter().peekable();
+        let mut merged = Vec::with_capacity(left.len() + right.len());
+        loop {
+            let next = match (left.peek(), right.peek()) {
+                (Some(a), Some(b)) if b < a => right.next(),
+                (Some(_), _) => left.next(),
+                (None, _) => right.next(),
+            };
+            match next {
+                Some(leaf) => merged.push(leaf),
+                None => break,
+            }
+        }
+        Self::with_hasher(merged, self.config)
+    }
+
+    /// Proves `transaction` is absent from a tree whose leaves are sorted, see
+    /// [`MerkleTree::insert_sorted`]. Returns `None` if it is present or the tree has no leaf
+    /// values.
+    pub fn proof_of_absence(&self, transaction: &H) -> Option<AbsenceProof<H>>
+    where
+        H: Ord + Clone,
+    {
+        self.leafs.as_ref()?;
+        let leaf = |index: usize| self.get_leaf(LeafIndex(index));
+        // The leaves are sorted by tree position, which isn't their order in leafs with an order
+        let (mut index, mut end) = (0, self.leaf_hashes.len());
+        while index < end {
+            let middle = index + (end - index) / 2;
+            if leaf(middle) < Some(transaction) {
+                index = middle + 1;
+            } else {
+                end = middle;
+            }
+        }
+        if leaf(index) == Some(transaction) {
+            return None;
+        }
+
+        let neighbour =
+            |index: usize| Some((leaf(index)?.clone(), self.proof_for(LeafIndex(index))?));
+        Some(AbsenceProof {
+            lower: index.checked_sub(1).and_then(neighbour),
+            upper: neighbour(index),
+        })
+    }
+
+    /// Always fails on trees whose config doesn't give every level of a proof exactly one sibling,
+    /// as the positions of the neighbours can't be checked.
+    pub fn verify_absence(&self, transaction: &H, proof: &AbsenceProof<H>) -> bool
+    where
+        H: Ord,
+    {
+        if !self.config.has_positional_proofs() {
+            return false;
+        }
+        // Checks the neighbour is in the tree and returns its position
+        let proven_index = |(leaf, siblings): &(H, Vec<SiblingHash>)| {
+            let index = proof_index(siblings).filter(|index| *index < self.leaf_hashes.len())?;
+            self.proves(self.hash_leaf(leaf), siblings).then_some(index)
+        };
+        let lower = match &proof.lower {
+            Some(lower) if lower.0 < *transaction => proven_index(lower).map(Some),
+            Some(_) => None,
+            None => Some(None),
+        };
+        let upper = match &proof.upper {
+            Some(upper) if *transaction < upper.0 => proven_index(upper).map(Some),
+            Some(_) => None,
+            None => Some(None),
+        };
+
+        // The neighbours have to be adjacent, or the first or last leaf when one is missing
+        match (lower, upper) {
+            (Some(Some(lower)), Some(Some(upper))) => lower + 1 == upper,
+            (Some(None), Some(None)) => self.leaf_hashes.is_empty(),
+            (Some(None), Some(Some(upper))) => upper == 0,
+            (Some(Some(lower)), Some(None)) => lower + 1 == self.leaf_hashes.len(),
+            _ => false,
+        }
+    }
+
+    /// Starts staging mutations: until [`MerkleTree::commit`] or [`MerkleTree::rollback`], every
+    /// mutation, [`MerkleTree::add`] included, only changes the leaves, and the root listener
+    /// isn't called. Reading the tree meanwhile rebuilds it over the staged leaves. Calling it
+    /// again while staging keeps the first starting point.
+    pub fn begin(&mut self)
+    where
+        H: Clone,
+    {
+        if self.staged.is_none() {
+            self.staged = Some(Staged {
+                levels: self.levels.get().cloned(),
+                leaf_hashes: self.leaf_hashes.clone(),
+                leafs: self.leafs.clone(),
+                order: self.order.clone(),
+            });
+        }
+    }
+
+    /// Applies the mutations staged since [`MerkleTree::begin`], rebuilding the tree once. If the
+    /// staged leaves can't be built, such as leaves left empty through [`MerkleTree::leaves_mut`],
+    /// the tree is rolled back and the error returned.
+    pub fn commit(&mut self) -> Result<(), MerkleError> {
+        let Some(staged) = self.staged.take() else {
+            return self.ensure_built().map(|_| ());
+        };
+        if let Err(error) = self.ensure_built() {
+            self.restore(staged);
+            return Err(error);
+        }
+        self.notify_root_change();
+        Ok(())
+    }
+
+    /// Discards the mutations staged since [`MerkleTree::begin`], bringing back the leaves and the
+    /// root of the tree then. The version still moves, as proofs may have been issued over the
+    /// staged leaves.
+    pub fn rollback(&mut self) {
+        if let Some(staged) = self.staged.take() {
+            self.restore(staged);
+        }
+    }
+
+    fn restore(&mut self, staged: Staged<H>) {
+        self.levels = staged.levels.map_or_else(OnceLock::new, OnceLock::from);
+        self.leaf_hashes = staged.leaf_hashes;
+        self.leafs = staged.leafs;
+        self.order = staged.order;
+        self.leaf_index = OnceLock::new();
+        self.mutated();
+    }
+
+    /// Lets the caller edit the leaves freely (swap, sort, splice...) and pay for a single rebuild.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree has no leaf values, as it was built with
+    /// [`MerkleTree::from_leaf_hashes`] or pruned with [`MerkleTree::prune_leaves`], see
+    /// [`MerkleTree::try_leaves_mut`].
+    pub fn leaves_mut(&mut self) -> LeavesGuard<'_, H, M> {
+        self.try_leaves_mut()
+            .expect("a tree without leaf values has none to edit")
+    }
+
+    /// Like [`MerkleTree::leaves_mut`], failing with [`MerkleError::NoLeafValues`] instead of
+    /// panicking when the tree has no leaf values.
+    pub fn try_leaves_mut(&mut self) -> Result<LeavesGuard<'_, H, M>, MerkleError> {
+        self.apply_order();
+        let leafs = self.leafs.take().ok_or(MerkleError::NoLeafValues)?;
+        Ok(LeavesGuard {
+            merkle_tree: self,
+            leafs,
+        })
+    }
+}
+
+// Parents fold their sons from the left, so the sons before a node become a single left sibling and
+// every son after it is a right sibling. In a binary tree that is the other half of its pair, or
+// itself when it is a lone node
+fn write_proof<M: MerkleHasher>(
+    levels: &Levels,
+    config: &MerkleConfig,
+    mut index: usize,
+    proof: &mut Vec<SiblingHash>,
+) {
+    let arity = config.arity;
+    for nodes in &levels[..levels.len() - 1] {
+        let group_start = index - index % arity;
+        let group = &nodes[group_start..nodes.len().min(group_start + arity)];
+        let mut sons = config.padded_group(group);
+
+        let left_sons = sons.by_ref().take(index - group_start).reduce(M::hash_pair);
+        proof.extend(left_sons.map(SiblingHash::Left));
+        sons.next();
+        proof.extend(sons.map(SiblingHash::Right));
+        index /= arity;
+    }
+}
+
+impl<H: LeafEncode, M: MerkleHasher> Deref for LeavesGuard<'_, H, M> {
+    type Target = Vec<H>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.leafs
+    }
+}
+
+impl<H: LeafEncode, M: MerkleHasher> DerefMut for LeavesGuard<'_, H, M> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.leafs
+    }
+}
+
+impl<H: LeafEncode, M: MerkleHasher> Drop for LeavesGuard<'_, H, M> {
+    fn drop(&mut self) {
+        let leafs = mem::take(&mut self.leafs);
+        self.merkle_tree.leaf_hashes =
+            MerkleTree::<H, M>::get_hashes_of_transactions(&leafs, &self.merkle_tree.config);
+        self.merkle_tree.leafs = Some(leafs);
+        self.merkle_tree.invalidate();
+    }
+}
+
+// Folds like fold_proof_with, but gives no root at all for a proof with a sibling strict mode
+// rejects, see MerkleConfig::reject_zero_siblings
+fn fold_strict<M: MerkleHasher, S: Borrow<SiblingHash>>(
+    config: &MerkleConfig,
+    hash: Hash,
+    proof: impl IntoIterator<Item = S>,
+) -> Option<Hash> {
+    let mut suspicious = false;
+    let proof = proof
+        .into_iter()
+        .inspect(|sibling| suspicious |= config.is_suspicious_sibling(sibling.borrow()));
+    let root = fold_proof_with::<M, _>(hash, proof);
+    (!suspicious).then_some(root)
+}
+
+// Accepts an optional 0x prefix, but unlike from_str_radix no sign
+fn parse_hex(hex: &str) -> Result<Hash, MerkleError> {
+    let digits = hex.strip_prefix("0x").unwrap_or(hex);
+    if digits.is_empty() || !digits.chars().all(|digit| digit.is_ascii_hexdigit()) {
+        return Err(MerkleError::BadHex(hex.to_string()));
+    }
+    u64::from_str_radix(digits, 16)
+        .map(Hash::from)
+        .map_err(|_| MerkleError::BadHex(hex.to_string()))
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use std::collections::HashMap;
+    use std::hash::{DefaultHasher, Hasher};
+    use std::io::Cursor;
+    use std::sync::atomic::Ordering;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use proptest::prelude::*;
+
+    use crate::config::{MerkleConfig, OddStrategy};
+    use crate::error::{MerkleError, StructuralError};
+    use crate::hash::Hash;
+    use crate::hasher::{DefaultMerkleHasher, MerkleHasher};
+    use crate::leaf::LeafEncode;
+    use crate::merkle_tree::{
+        AbsenceProof, BuildWarning, LeafIndex, MerkleTree, ProofDiagnostic, ProofImpact,
+        SiblingHash, VerifyOutcome, Witness,
+    };
+    use crate::proof::{fold_proof, proof_index, verify_proof, CompactProof, Proof};
+    use crate::tree_head::{commitments_match, DatasetCommitment, TreeHead};
+
+    // Nodes hash to the xor of their sons, so two equal sons make a zero node
+    #[derive(Default)]
+    struct XorMerkleHasher(u64);
+
+    impl MerkleHasher for XorMerkleHasher {
+        const ALGORITHM: u8 = 0;
+        const EMPTY_ROOT: Hash = Hash::new(0);
+
+        fn write(&mut self, bytes: &[u8]) {
+            for byte in bytes {
+                self.0 = self.0.rotate_left(8) ^ u64::from(*byte);
+            }
+        }
+
+        fn finish(self) -> Hash {
+            Hash::from(self.0)
+        }
+
+        fn hash_pair(left: Hash, right: Hash) -> Hash {
+            Hash::from(u64::from(left) ^ u64::from(right))
+        }
+    }
+
+    #[test]
+    fn cant_create_a_merkle_tree_without_transactions() {
+        let transactions: Vec<String> = Vec::new();
+        let merkle_tree = MerkleTree::new(transactions);
+
+        assert!(merkle_tree.is_err());
+    }
+
+    #[test]
+    fn a_merkle_tree_without_transactions_reports_that_a_leaf_is_required() {
+        let Err(error) = MerkleTree::new(Vec::<u32>::new()) else {
+            panic!("a tree was built without transactions");
+        };
+
+        assert_eq!(error, MerkleError::EmptyTransactions);
+        assert!(error.to_string().contains("at least one leaf is required"));
+    }
+
+    #[test]
+    fn an_empty_merkle_tree_has_the_empty_root() {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&[]);
+        assert_eq!(MerkleTree::<&str>::EMPTY_ROOT, Hash::from(hasher.finish()));
+
+        let mut merkle_tree = MerkleTree::allow_empty(Vec::new()).unwrap();
+        assert_eq!(merkle_tree.root(), MerkleTree::<&str>::EMPTY_ROOT);
+        assert!(!merkle_tree.verify_iter("", Vec::new()));
+        let absence = merkle_tree.proof_of_absence(&"A").unwrap();
+        assert!(merkle_tree.verify_absence(&"A", &absence));
+
+        merkle_tree.add("A").unwrap();
+        assert_eq!(merkle_tree.root(), MerkleTree::leaf_hash(&"A"));
+        merkle_tree.add("B").unwrap();
+        assert_eq!(
+            merkle_tree.root(),
+            MerkleTree::new(vec!["A", "B"]).unwrap().root()
+        );
+    }
+
+    #[test]
+    fn a_merkle_tree_can_contain_one_transaction() {
+        let transactions = vec![String::from("A")];
+        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+        let transaction = transactions[0].clone();
+        let proof = merkle_tree.get_proof(transaction.clone());
+
+        assert!(merkle_tree.verify(transaction, proof))
+    }
+
+    #[test]
+    fn a_merkle_tree_can_contain_one_level_of_transactions() {
+        let transactions = vec![String::from("A"), String::from("B")];
+        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+        let transaction = transactions[0].clone();
+        let proof = merkle_tree.get_proof(transaction.clone());
+
+        assert!(merkle_tree.verify(transaction, proof));
+    }
+    #[test]
+    fn a_merkle_tree_can_contain_two_level_of_transactions() {
+        let transactions = vec![
+            String::from("A"),
+            String::from("B"),
+            String::from("C"),
+            String::from("D"),
+        ];
+        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+        let transaction = transactions[0].clone();
+        let proof = merkle_tree.get_proof(transaction.clone());
+
+        assert!(merkle_tree.verify(transaction, proof));
+    }
+
+    #[test]
+    fn a_merkle_tree_can_contain_an_odd_number_of_transactions() {
+        let transactions = vec![String::from("A"), String::from("B"), String::from("C")];
+        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+        let transaction = transactions[0].clone();
+        let proof = merkle_tree.get_proof(transaction.clone());
+
+        assert!(merkle_tree.verify(transaction, proof));
+    }
+    #[test]
+    fn a_lone_transaction_is_proven_with_itself_as_its_right_sibling() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        let leaf_hash = MerkleTree::leaf_hash;
+        let left_hash = DefaultMerkleHasher::hash_pair(leaf_hash(&"A"), leaf_hash(&"B"));
+        let proof = merkle_tree.get_proof("C");
+
+        assert_eq!(
+            proof,
+            vec![
+                SiblingHash::Right(leaf_hash(&"C")),
+                SiblingHash::Left(left_hash)
+            ]
+        );
+        assert_eq!(proof, merkle_tree.proof_for(LeafIndex(2)).unwrap());
+        assert_eq!(
+            merkle_tree.root(),
+            DefaultMerkleHasher::hash_pair(
+                left_hash,
+                DefaultMerkleHasher::hash_pair(leaf_hash(&"C"), leaf_hash(&"C"))
+            )
+        );
+        assert!(merkle_tree.verify("C", proof));
+
+        // The lone node can also be a whole sub-tree, higher up
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(4)).unwrap();
+        let lone_hash = DefaultMerkleHasher::hash_pair(leaf_hash(&"E"), leaf_hash(&"E"));
+        assert_eq!(
+            proof[..2],
+            [
+                SiblingHash::Right(leaf_hash(&"E")),
+                SiblingHash::Right(lone_hash)
+            ]
+        );
+        assert!(merkle_tree.verify_iter("E", proof));
+    }
+
+    #[test]
+    fn a_merkle_tree_can_contain_multiple_levels_of_transactions() {
+        let transactions = vec![
+            String::from("A"),
+            String::from("B"),
+            String::from("C"),
+            String::from("D"),
+            String::from("E"),
+            String::from("F"),
+        ];
+        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+        let transaction = transactions[0].clone();
+        let proof = merkle_tree.get_proof(transaction.clone());
+
+        assert!(merkle_tree.verify(transaction, proof));
+    }
+
+    #[test]
+    fn a_merkle_tree_can_add_new_elements() {
+        let transactions = vec![String::from("A")];
+        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+        let transaction = transactions[0].clone();
+        let proof = merkle_tree.get_proof(transaction.clone());
+
+        assert_eq!(proof.len(), 0);
+        assert!(merkle_tree.verify(transaction, proof));
+
+        merkle_tree.add(String::from("B")).unwrap();
+        let transaction = transactions[0].clone();
+        let proof = merkle_tree.get_proof(transaction.clone());
+        assert_eq!(proof.len(), 1);
+        assert!(merkle_tree.verify(transaction, proof));
+    }
+
+    #[test]
+    fn a_merkle_tree_cant_verify_a_transaction_if_not_present() {
+        let transactions = vec![String::from("A"), String::from("B")];
+        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+        let transaction = String::from("C");
+        let proof = merkle_tree.get_proof(transaction.clone());
+
+        assert!(!merkle_tree.verify(transaction, proof));
+    }
+
+    #[test]
+    fn a_merkle_tree_can_have_generic_transactions() {
+        let transactions: Vec<u32> = vec![1000, 1500, 2000, 3000, 4000, 5500, 7000, 8700];
+        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+        let transaction = transactions[0];
+        let proof = merkle_tree.get_proof(transaction);
+
+        assert!(merkle_tree.verify(transaction, proof));
+        // Integers are hashed as big endian bytes, so their leaves hash alike on every platform
+        assert_eq!(transaction.encode(), transaction.to_be_bytes());
+        assert_eq!(
+            merkle_tree.leaf_hashes()[0],
+            MerkleTree::<Vec<u8>>::leaf_hash(&transaction.to_be_bytes().to_vec())
+        );
+
+        let transactions = vec![
+            "De aquel amor",
+            "De musica ligera",
+            "Nada nos libra,",
+            "Nada mas queda",
+        ];
+        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+        let transaction = transactions[0];
+        let proof = merkle_tree.get_proof(transaction);
+
+        assert!(merkle_tree.verify(transaction, proof));
+    }
+
+    #[test]
+    fn a_merkle_tree_can_prove_a_transaction_by_its_index() {
+        for size in 1..=7 {
+            let transactions: Vec<usize> = (0..size).collect();
+            let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+
+            for (index, transaction) in transactions.into_iter().enumerate() {
+                let proof = merkle_tree.proof_for(LeafIndex(index)).unwrap();
+                assert!(merkle_tree.verify(transaction, proof));
+            }
+            assert!(merkle_tree.proof_for(LeafIndex(size)).is_none());
+        }
+    }
+
+    #[test]
+    fn a_merkle_tree_grown_one_leaf_at_a_time_has_the_same_root_as_one_built_at_once() {
+        let mut merkle_tree = MerkleTree::new(vec![0]).unwrap();
+        for size in 2..=1000 {
+            merkle_tree.add(size - 1).unwrap();
+            let expected = MerkleTree::new((0..size).collect()).unwrap();
+
+            assert_eq!(merkle_tree.root(), expected.root());
+            assert_eq!(merkle_tree.height(), expected.height());
+        }
+        // Adding to a built tree never rebuilds it
+        assert_eq!(merkle_tree.rebuilds.load(Ordering::Relaxed), 0);
+        let proof = merkle_tree.proof_for(LeafIndex(637)).unwrap();
+        assert!(merkle_tree.verify(637, proof));
+    }
+
+    #[test]
+    fn a_merkle_tree_can_be_configured_with_another_arity_and_odd_strategy() {
+        let config = MerkleConfig::default()
+            .arity(4)
+            .odd_strategy(OddStrategy::ZeroPad);
+        let transactions: Vec<u32> = (0..11).collect();
+        let mut merkle_tree = MerkleTree::with_config(transactions.clone(), config).unwrap();
+        assert_eq!(merkle_tree.height(), 2);
+
+        for strategy in [
+            OddStrategy::Duplicate,
+            OddStrategy::ZeroPad,
+            OddStrategy::Promote,
+        ] {
+            for arity in 2..=5 {
+                let config = MerkleConfig::default().arity(arity).odd_strategy(strategy);
+                let merkle_tree = MerkleTree::with_config(transactions.clone(), config).unwrap();
+                for (index, transaction) in transactions.iter().enumerate() {
+                    let proof = merkle_tree.proof_for(LeafIndex(index)).unwrap();
+                    assert!(merkle_tree.verify_iter(*transaction, proof));
+                }
+
+                let mut grown = MerkleTree::with_config(vec![0], config).unwrap();
+                for transaction in 1..11 {
+                    grown.add(transaction).unwrap();
+                }
+                assert_eq!(grown.root(), merkle_tree.root());
+            }
+        }
+
+        merkle_tree.add(11).unwrap();
+        let expected = MerkleTree::with_config((0..12).collect(), config).unwrap();
+        assert_eq!(merkle_tree.root(), expected.root());
+        let proof = merkle_tree.try_get_proof(&11).unwrap();
+        assert!(merkle_tree.verify(11, proof));
+
+        assert_ne!(
+            merkle_tree.root(),
+            MerkleTree::new((0..12).collect()).unwrap().root()
+        );
+        assert_eq!(
+            MerkleTree::with_config(vec![1], MerkleConfig::default().arity(1)).err(),
+            Some(MerkleError::BadArity(1))
+        );
+    }
+
+    #[test]
+    fn a_merkle_tree_can_update_a_transaction_by_its_index() {
+        let transactions = vec![String::from("A"), String::from("B"), String::from("C")];
+        let mut merkle_tree = MerkleTree::new(transactions).unwrap();
+
+        merkle_tree.update(LeafIndex(1), String::from("D")).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex::from(1)).unwrap();
+
+        assert_eq!(merkle_tree.get_leaf(LeafIndex(1)), Some(&String::from("D")));
+        assert!(merkle_tree.verify(String::from("D"), proof));
+        assert_eq!(
+            merkle_tree.update(LeafIndex(3), String::from("E")),
+            Err(MerkleError::IndexOutOfBounds(3))
+        );
+    }
+
+    #[test]
+    fn a_merkle_tree_reports_a_reversed_proof() {
+        let transactions = vec!["A", "B", "C", "D"];
+        let merkle_tree = MerkleTree::new(transactions).unwrap();
+        let mut proof = merkle_tree.proof_for(LeafIndex(1)).unwrap();
+
+        assert_eq!(
+            merkle_tree.verify_diagnostic("B", &proof),
+            ProofDiagnostic::Valid
+        );
+
+        proof.reverse();
+        assert_eq!(
+            merkle_tree.verify_diagnostic("B", &proof),
+            ProofDiagnostic::Reversed
+        );
+        assert_eq!(
+            merkle_tree.verify_diagnostic("E", &proof),
+            ProofDiagnostic::Invalid
+        );
+    }
+
+    #[test]
+    fn a_binary_merkle_tree_has_the_same_root_on_both_build_paths() {
+        let strategies = [
+            OddStrategy::Duplicate,
+            OddStrategy::ZeroPad,
+            OddStrategy::Promote,
+        ];
+        for (size, odd_strategy) in [2, 3, 4, 5, 7, 8, 13, 16]
+            .into_iter()
+            .flat_map(|size| strategies.map(|odd_strategy| (size, odd_strategy)))
+        {
+            let config = MerkleConfig::default().odd_strategy(odd_strategy);
+            let transactions: Vec<u32> = (0..size as u32).collect();
+            let leaves = || MerkleTree::<u32>::get_hashes_of_transactions(&transactions, &config);
+
+            let general_levels = MerkleTree::<u32>::create_levels(leaves(), size, &config).unwrap();
+            let pairs_levels =
+                MerkleTree::<u32>::create_levels_from_pairs(leaves(), size, &config).unwrap();
+            let merkle_tree = MerkleTree::with_config(transactions.clone(), config).unwrap();
+
+            assert_eq!(general_levels, pairs_levels);
+            assert_eq!(merkle_tree.root(), pairs_levels[pairs_levels.len() - 1][0]);
+        }
+    }
+
+    #[test]
+    fn a_detached_proof_is_rejected_if_built_with_another_algorithm() {
+        let transactions = vec!["A", "B", "C"];
+        let merkle_tree = MerkleTree::new(transactions).unwrap();
+        let root = merkle_tree.root();
+        let mut bytes = merkle_tree.detached_proof(LeafIndex(2)).unwrap().to_bytes();
+
+        let proof = Proof::from_bytes(&bytes).unwrap();
+        assert_eq!(MerkleTree::verify_detached(&"C", &proof, root), Ok(true));
+
+        bytes[0] = MerkleTree::<&str>::ALGORITHM + 1;
+        let proof = Proof::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            MerkleTree::verify_detached(&"C", &proof, root),
+            Err(MerkleError::AlgorithmMismatch {
+                expected: MerkleTree::<&str>::ALGORITHM,
+                found: MerkleTree::<&str>::ALGORITHM + 1,
+            })
+        );
+    }
+
+    #[test]
+    fn a_sorted_merkle_tree_can_prove_a_transaction_is_absent() {
+        let mut merkle_tree = MerkleTree::new(vec![40]).unwrap();
+        assert_eq!(merkle_tree.insert_sorted(20), Ok(LeafIndex(0)));
+        assert_eq!(merkle_tree.insert_sorted(50), Ok(LeafIndex(2)));
+        assert_eq!(merkle_tree.insert_sorted(10), Ok(LeafIndex(0)));
+        assert_eq!(merkle_tree.insert_sorted(30), Ok(LeafIndex(2)));
+        assert_eq!(merkle_tree.leaves(), Some(&[10, 20, 30, 40, 50][..]));
+
+        for absent in [5, 25, 35, 45, 55] {
+            let proof = merkle_tree.proof_of_absence(&absent).unwrap();
+            assert!(merkle_tree.verify_absence(&absent, &proof));
+        }
+        assert!(merkle_tree.proof_of_absence(&30).is_none());
+
+        // Neighbours that are in the tree but not next to each other leave a gap to hide a leaf in
+        let gapped = AbsenceProof {
+            lower: merkle_tree.proof_of_absence(&15).unwrap().lower,
+            upper: merkle_tree.proof_of_absence(&35).unwrap().upper,
+        };
+        assert!(!merkle_tree.verify_absence(&30, &gapped));
+        let proof = merkle_tree.proof_of_absence(&55).unwrap();
+        assert!(!merkle_tree.verify_absence(&45, &proof));
+
+        merkle_tree.prune_leaves();
+        assert_eq!(
+            merkle_tree.insert_sorted(60),
+            Err(MerkleError::NoLeafValues)
+        );
+    }
+
+    #[test]
+    fn merkle_trees_commitments_match_only_with_the_same_leaves_in_the_same_order() {
+        let commitment = MerkleTree::new(vec!["A", "B", "C"]).unwrap().commitment();
+
+        let same = MerkleTree::new(vec!["A", "B", "C"]).unwrap().commitment();
+        assert!(commitments_match(&commitment, &same));
+
+        let reordered = MerkleTree::new(vec!["B", "A", "C"]).unwrap().commitment();
+        assert!(!commitments_match(&commitment, &reordered));
+
+        // The lone leaf is duplicated, so a copy of it at the end gives the same root
+        let resized = MerkleTree::new(vec!["A", "B", "C", "C"])
+            .unwrap()
+            .commitment();
+        assert_eq!(commitment.root, resized.root);
+        assert!(!commitments_match(&commitment, &resized));
+
+        let other_algorithm = DatasetCommitment {
+            algorithm: MerkleTree::<&str>::ALGORITHM + 1,
+            ..commitment
+        };
+        assert!(!commitments_match(&commitment, &other_algorithm));
+    }
+
+    #[test]
+    fn a_merkle_tree_can_verify_a_proof_given_as_an_iterator() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        let proof = merkle_tree.get_proof("D");
+
+        assert!(merkle_tree.verify_iter("D", proof.clone().into_iter().rev().rev()));
+        assert!(!merkle_tree.verify_iter("D", proof.clone().into_iter().rev()));
+        assert!(!merkle_tree.verify_iter("C", proof.clone()));
+        assert!(merkle_tree.verify("D", proof));
+    }
+
+    #[test]
+    fn a_merkle_tree_detects_leaves_with_colliding_hashes() {
+        // Only the bucket is encoded, so leaves in the same bucket collide
+        #[derive(Clone, PartialEq)]
+        struct Bucketed(u32, u32);
+        impl LeafEncode for Bucketed {
+            fn encode(&self) -> Vec<u8> {
+                self.0.encode()
+            }
+        }
+
+        let distinct = MerkleTree::new(vec!["A", "B", "C", "A"]).unwrap();
+        assert!(distinct.detect_collisions().is_empty());
+
+        let transactions = vec![
+            Bucketed(0, 1),
+            Bucketed(1, 1),
+            Bucketed(0, 2),
+            Bucketed(0, 1),
+            Bucketed(1, 3),
+        ];
+        let merkle_tree = MerkleTree::new(transactions).unwrap();
+        assert_eq!(
+            merkle_tree.detect_collisions(),
+            vec![(0, 2), (1, 4), (2, 3)]
+        );
+    }
+
+    #[test]
+    fn a_pruned_merkle_tree_still_serves_proofs() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        let root = merkle_tree.root();
+        merkle_tree.prune_leaves();
+
+        assert_eq!(merkle_tree.root(), root);
+        for (index, transaction) in ["A", "B", "C", "D", "E"].into_iter().enumerate() {
+            let proof = merkle_tree.proof_for(LeafIndex(index)).unwrap();
+            assert!(merkle_tree.verify_iter(transaction, proof));
+        }
+        assert_eq!(
+            merkle_tree.try_get_proof(&"C"),
+            Ok(merkle_tree.proof_for(LeafIndex(2)).unwrap())
+        );
+
+        assert_eq!(merkle_tree.get_leaf(LeafIndex(0)), None);
+        assert_eq!(merkle_tree.leaves(), None);
+        merkle_tree.add("F").unwrap();
+        assert_eq!(merkle_tree.try_get_proof(&"F").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn a_merkle_tree_counts_the_hashes_needed_to_verify_a_proof() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(4)).unwrap();
+
+        assert_eq!(
+            merkle_tree.verify_counted("E", &proof),
+            (true, proof.len() + 1)
+        );
+        assert_eq!(
+            merkle_tree.verify_counted("A", &proof),
+            (false, proof.len() + 1)
+        );
+        assert_eq!(merkle_tree.verify_counted("E", &[]), (false, 1));
+    }
+
+    #[test]
+    fn a_merkle_tree_built_from_leaf_hashes_matches_one_built_from_values() {
+        let transactions: Vec<u32> = (0..13).collect();
+        let config = MerkleConfig::default().arity(3);
+        let from_values = MerkleTree::with_config(transactions.clone(), config).unwrap();
+        let leaf_hashes =
+            MerkleTree::<u32>::get_hashes_of_transactions(&transactions, &MerkleConfig::default());
+        let from_hashes = MerkleTree::<u32>::build_from_leaf_hashes(leaf_hashes, config).unwrap();
+
+        assert_eq!(from_hashes.root(), from_values.root());
+        assert_eq!(from_hashes.leaves(), None);
+        for index in 0..transactions.len() {
+            assert_eq!(
+                from_hashes.proof_for(LeafIndex(index)),
+                from_values.proof_for(LeafIndex(index))
+            );
+        }
+        assert_eq!(
+            MerkleTree::<u32>::build_from_leaf_hashes(Vec::new(), config).err(),
+            Some(MerkleError::EmptyTransactions)
+        );
+    }
+
+    #[test]
+    fn a_merkle_tree_can_be_rebuilt_from_its_array_of_nodes() {
+        for leaf_count in 1..=9 {
+            let merkle_tree = MerkleTree::new((0..leaf_count).collect()).unwrap();
+            let array = merkle_tree.to_array();
+            assert_eq!(array[0], merkle_tree.root());
+
+            let rebuilt = MerkleTree::<i32>::from_array(array, leaf_count as usize).unwrap();
+            assert_eq!(rebuilt.root(), merkle_tree.root());
+            for index in 0..leaf_count as usize {
+                assert_eq!(
+                    rebuilt.proof_for(LeafIndex(index)),
+                    merkle_tree.proof_for(LeafIndex(index))
+                );
+            }
+        }
+
+        let array = MerkleTree::new(vec!["A", "B", "C"]).unwrap().to_array();
+        assert_eq!(
+            MerkleTree::<&str>::from_array(array, 5).err(),
+            Some(MerkleError::BadArrayLength {
+                expected: 15,
+                found: 7
+            })
+        );
+    }
+
+    #[test]
+    fn salted_merkle_trees_have_proofs_that_only_verify_with_their_salt() {
+        let one_tree = MerkleTree::with_salt(vec![false, true], 1).unwrap();
+        let other_tree = MerkleTree::with_salt(vec![false, true], 2).unwrap();
+        let unsalted = MerkleTree::new(vec![false, true]).unwrap();
+
+        assert_ne!(one_tree.leaf_hashes, other_tree.leaf_hashes);
+        assert_ne!(one_tree.leaf_hashes[1], MerkleTree::leaf_hash(&true));
+        assert_ne!(one_tree.root(), unsalted.root());
+
+        let proof = one_tree.proof_for(LeafIndex(1)).unwrap();
+        assert!(one_tree.verify_iter(true, proof.clone()));
+        assert!(!other_tree.verify_iter(true, proof));
+
+        let proof = one_tree.detached_proof(LeafIndex(1)).unwrap();
+        assert_eq!(proof.salt, Some(1));
+        assert_eq!(
+            MerkleTree::verify_detached(&true, &proof, one_tree.root()),
+            Ok(true)
+        );
+        assert_eq!(
+            MerkleTree::verify_detached(&true, &proof, other_tree.root()),
+            Ok(false)
+        );
+
+        let salted = MerkleTree::new_salted(vec![false, true]).unwrap();
+        assert!(salted.salt().is_some());
+        assert_eq!(salted.try_get_proof(&true).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn a_proof_issued_before_a_mutation_is_reported_as_stale() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        let proof = merkle_tree.detached_proof(LeafIndex(1)).unwrap();
+        assert_eq!(merkle_tree.verify_versioned(&"B", &proof), Ok(true));
+
+        merkle_tree.add("D").unwrap();
+        assert_eq!(merkle_tree.version(), 1);
+        assert_eq!(
+            merkle_tree.verify_versioned(&"B", &proof),
+            Err(MerkleError::StaleProof {
+                issued: 0,
+                current: 1
+            })
+        );
+
+        let proof = merkle_tree.detached_proof(LeafIndex(1)).unwrap();
+        assert_eq!(merkle_tree.verify_versioned(&"B", &proof), Ok(true));
+    }
+
+    #[test]
+    fn a_proof_claiming_an_index_past_the_last_leaf_is_rejected() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        let tree_head = merkle_tree.tree_head_at(1_700_000_000);
+        let proof = merkle_tree.proof_for(LeafIndex(2)).unwrap();
+
+        assert_eq!(merkle_tree.verify_at(&"C", LeafIndex(2), &proof), Ok(true));
+        assert_eq!(merkle_tree.verify_at(&"C", LeafIndex(3), &proof), Ok(false));
+        assert_eq!(
+            merkle_tree.verify_at(&"C", LeafIndex(10), &proof),
+            Err(MerkleError::IndexOutOfBounds(10))
+        );
+
+        assert_eq!(
+            MerkleTree::verify_with_size(&"C", LeafIndex(2), &proof, &tree_head),
+            Ok(true)
+        );
+        assert_eq!(
+            MerkleTree::verify_with_size(&"C", LeafIndex(5), &proof, &tree_head),
+            Err(MerkleError::IndexOutOfBounds(5))
+        );
+    }
+
+    #[test]
+    fn merkle_trees_with_the_same_state_have_the_same_tree_head() {
+        let one_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        let other_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        let tree_head = one_tree.tree_head_at(1_700_000_000);
+
+        assert_eq!(tree_head, other_tree.tree_head_at(1_700_000_000));
+        assert_eq!(
+            tree_head.to_bytes(),
+            other_tree.tree_head_at(1_700_000_000).to_bytes()
+        );
+        assert_eq!(tree_head.size, 3);
+
+        let proof = one_tree.proof_for(LeafIndex(1)).unwrap();
+        assert!(MerkleTree::verify_with_tree_head(&"B", &proof, &tree_head));
+        assert!(!MerkleTree::verify_with_tree_head(
+            &"B",
+            &proof,
+            &TreeHead::at(Hash::default(), 3, 1_700_000_000)
+        ));
+    }
+
+    #[test]
+    fn a_merkle_tree_can_write_proofs_into_the_same_buffer() {
+        let transactions = vec!["A", "B", "C", "D"];
+        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+        let mut buf = Vec::new();
+
+        for transaction in transactions {
+            merkle_tree.get_proof_into(&transaction, &mut buf);
+            assert_eq!(buf.len(), 2);
+            assert!(merkle_tree.verify(transaction, buf.clone()));
+        }
+
+        merkle_tree.get_proof_into(&"E", &mut buf);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn a_merkle_tree_can_verify_a_proof_given_as_hex() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D"]).unwrap();
+        let root_hex = format!("{:016x}", merkle_tree.root());
+        let proof: Vec<(String, bool)> = merkle_tree
+            .proof_for(LeafIndex(2))
+            .unwrap()
+            .into_iter()
+            .map(|sibling_hash| match sibling_hash {
+                SiblingHash::Left(hash) => (format!("{hash:016x}"), true),
+                SiblingHash::Right(hash) => (format!("0x{hash:016X}"), false),
+            })
+            .collect();
+
+        assert_eq!(MerkleTree::verify_hex("C", &proof, &root_hex), Ok(true));
+        assert_eq!(MerkleTree::verify_hex("A", &proof, &root_hex), Ok(false));
+    }
+
+    #[test]
+    fn a_merkle_tree_rejects_a_proof_with_invalid_hex() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B"]).unwrap();
+        let root_hex = format!("{:016x}", merkle_tree.root());
+        let proof = vec![(String::from("+1f"), false)];
+
+        assert_eq!(
+            MerkleTree::verify_hex("A", &proof, &root_hex),
+            Err(MerkleError::BadHex(String::from("+1f")))
+        );
+        assert_eq!(
+            MerkleTree::verify_hex("A", &[], "not hex"),
+            Err(MerkleError::BadHex(String::from("not hex")))
+        );
+    }
+
+    #[test]
+    fn a_merkle_tree_never_has_more_levels_than_leaves() {
+        for size in 1..=64 {
+            let transactions: Vec<usize> = (0..size).collect();
+            let merkle_tree = MerkleTree::new(transactions).unwrap();
+
+            assert!(merkle_tree.height() <= size.ilog2() as usize + 1);
+        }
+
+        let leaves: Vec<Hash> = (0..5).map(Hash::from).collect();
+        assert_eq!(
+            MerkleTree::<u32>::create_levels(leaves, 2, &MerkleConfig::default()).err(),
+            Some(MerkleError::Internal(
+                "the tree has more levels than leaves"
+            ))
+        );
+    }
+
+    #[test]
+    fn a_merkle_tree_is_rebuilt_once_after_editing_its_leaves() {
+        let mut merkle_tree = MerkleTree::new(vec!["D", "B", "A"]).unwrap();
+
+        {
+            let mut leaves = merkle_tree.leaves_mut();
+            leaves.push("C");
+            leaves.sort();
+            leaves.swap(0, 1);
+            leaves[3] = "E";
+        }
+
+        let expected = MerkleTree::new(vec!["B", "A", "C", "E"]).unwrap();
+        assert_eq!(merkle_tree.root(), expected.root());
+        assert_eq!(merkle_tree.rebuilds.load(Ordering::Relaxed), 1);
+        assert_eq!(merkle_tree.get_leaf(LeafIndex(3)), Some(&"E"));
+
+        let proof = merkle_tree.proof_for(LeafIndex(0)).unwrap();
+        assert!(merkle_tree.verify("B", proof));
+
+        assert!(merkle_tree.try_leaves_mut().is_ok());
+        merkle_tree.prune_leaves();
+        assert!(matches!(
+            merkle_tree.try_leaves_mut(),
+            Err(MerkleError::NoLeafValues)
+        ));
+    }
+
+    #[test]
+    fn a_merkle_tree_without_leaf_values_cant_be_rehashed() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        assert!(merkle_tree.rehash::<DefaultMerkleHasher>().is_ok());
+        merkle_tree.prune_leaves();
+        assert!(matches!(
+            merkle_tree.rehash::<DefaultMerkleHasher>(),
+            Err(MerkleError::NoLeafValues)
+        ));
+        let from_hashes = MerkleTree::<&str>::from_leaf_hashes(vec![Hash::from(1)]).unwrap();
+        assert!(matches!(
+            from_hashes.rehash::<DefaultMerkleHasher>(),
+            Err(MerkleError::NoLeafValues)
+        ));
+    }
+
+    #[test]
+    fn a_merkle_tree_is_rebuilt_once_when_read_after_a_mutation() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B"]).unwrap();
+        merkle_tree.add("C").unwrap();
+        merkle_tree.add("D").unwrap();
+        merkle_tree.update(LeafIndex(0), "E").unwrap();
+        assert_eq!(merkle_tree.rebuilds.load(Ordering::Relaxed), 0);
+
+        let proof = merkle_tree.try_get_proof(&"C").unwrap();
+        assert!(merkle_tree.verify_diagnostic("C", &proof) == ProofDiagnostic::Valid);
+        assert_eq!(merkle_tree.try_get_proof(&"E").unwrap().len(), 2);
+        assert_eq!(merkle_tree.rebuilds.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn a_merkle_tree_can_serve_cached_proofs_to_several_threads() {
+        let merkle_tree = Arc::new(MerkleTree::new((0..100).collect()).unwrap());
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let merkle_tree = Arc::clone(&merkle_tree);
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        for transaction in 0..100 {
+                            let proof = merkle_tree.get_proof_cached(&transaction);
+                            assert_eq!(
+                                Some(&proof),
+                                merkle_tree
+                                    .proof_for(LeafIndex(transaction as usize))
+                                    .as_ref()
+                            );
+                            assert!(merkle_tree.verify_iter(transaction, proof));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let mut merkle_tree = Arc::into_inner(merkle_tree).unwrap();
+        merkle_tree.update(LeafIndex(3), 100).unwrap();
+        assert!(merkle_tree.get_proof_cached(&3).is_empty());
+        let proof = merkle_tree.get_proof_cached(&100);
+        assert!(merkle_tree.verify_iter(100, proof));
+    }
+
+    #[test]
+    fn a_merkle_tree_left_without_leaves_reports_it_when_read() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B"]).unwrap();
+        merkle_tree.leaves_mut().clear();
+
+        assert_eq!(
+            merkle_tree.try_get_proof(&"A"),
+            Err(MerkleError::EmptyTransactions)
+        );
+        assert_eq!(merkle_tree.try_root(), Err(MerkleError::EmptyTransactions));
+        assert!(merkle_tree.proof_for(LeafIndex(0)).is_none());
+        assert!(!merkle_tree.verify("A", Vec::new()));
+
+        merkle_tree.add("A").unwrap();
+        assert_eq!(merkle_tree.try_get_proof(&"A"), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn a_merkle_tree_root_is_the_hash_of_its_sons() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B"]).unwrap();
+
+        let leaf_hash = |transaction: &str| {
+            let mut hasher = DefaultHasher::new();
+            hasher.write(transaction.as_bytes());
+            hasher.finish()
+        };
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&leaf_hash("A").to_be_bytes());
+        hasher.write(&leaf_hash("B").to_be_bytes());
+
+        assert_eq!(u64::from(MerkleTree::leaf_hash(&"A")), leaf_hash("A"));
+        assert_eq!(merkle_tree.root(), Hash::from(hasher.finish()));
+    }
+
+    #[test]
+    fn an_indexed_merkle_tree_finds_proofs_by_value_without_searching() {
+        // Timing is left to the proof_by_value bench, this checks lookups go through the index
+        let config = MerkleConfig::default().index_leaves(true);
+        for height in [4, 12] {
+            let transactions: Vec<u64> = (0..1 << height).collect();
+            let last = *transactions.last().unwrap();
+            let mut indexed = MerkleTree::with_config(transactions.clone(), config).unwrap();
+            let mut searched = MerkleTree::new(transactions).unwrap();
+            assert!(indexed.leaf_index.get().is_none());
+
+            assert_eq!(indexed.get_proof(last), searched.get_proof(last));
+            assert_eq!(indexed.get_proof(0), searched.get_proof(0));
+            assert_eq!(
+                indexed.leaf_index.get().map(HashMap::len),
+                Some(1 << height)
+            );
+            assert!(searched.leaf_index.get().is_none());
+
+            indexed.add(last + 1).unwrap();
+            assert_eq!(
+                indexed.leaf_index.get().map(HashMap::len),
+                Some((1 << height) + 1)
+            );
+            let proof = indexed.get_proof(last + 1);
+            assert_eq!(
+                Some(proof.clone()),
+                indexed.proof_for(LeafIndex(1 << height))
+            );
+            assert!(indexed.verify(last + 1, proof));
+        }
+    }
+
+    #[test]
+    fn a_merkle_tree_calls_back_once_per_root_change() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B"]).unwrap();
+        let roots = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&roots);
+        merkle_tree.on_root_change(move |root| seen.lock().unwrap().push(root));
+
+        let mut expected = Vec::new();
+        merkle_tree.add("C").unwrap();
+        expected.push(merkle_tree.root());
+        // The lone C was already paired with itself, so a second one keeps the root
+        merkle_tree.add("C").unwrap();
+        merkle_tree.add("D").unwrap();
+        expected.push(merkle_tree.root());
+        merkle_tree.update(LeafIndex(0), "A").unwrap();
+        merkle_tree.update(LeafIndex(0), "E").unwrap();
+        expected.push(merkle_tree.root());
+
+        assert_eq!(*roots.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn a_merkle_tree_can_trace_the_hashes_folded_by_a_proof() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(2)).unwrap();
+
+        let (valid, trace) = MerkleTree::verify_trace("C", &proof, merkle_tree.root());
+        assert!(valid);
+        assert_eq!(trace.len(), proof.len() + 1);
+        assert_eq!(trace[0], MerkleTree::leaf_hash(&"C"));
+        assert_eq!(trace.last(), Some(&merkle_tree.root()));
+
+        let (valid, trace) = MerkleTree::verify_trace("D", &proof, merkle_tree.root());
+        assert!(!valid);
+        assert_eq!(trace.len(), proof.len() + 1);
+        assert_ne!(trace.last(), Some(&merkle_tree.root()));
+    }
+
+    #[test]
+    fn a_merkle_tree_rejects_a_truncated_proof_folding_to_its_root() {
+        let transactions: Vec<Vec<u8>> = ["A", "B", "C", "D"]
+            .iter()
+            .map(|transaction| transaction.encode())
+            .collect();
+        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+        let leaf_hashes: Vec<Hash> = transactions.iter().map(MerkleTree::leaf_hash).collect();
+
+        // Its leaf hash is the hash of the parent of A and B, so one sibling is enough to reach the
+        // root
+        let forged = [leaf_hashes[0].to_be_bytes(), leaf_hashes[1].to_be_bytes()].concat();
+        let truncated = vec![SiblingHash::Right(DefaultMerkleHasher::hash_pair(
+            leaf_hashes[2],
+            leaf_hashes[3],
+        ))];
+        assert!(merkle_tree.verify(forged.clone(), truncated.clone()));
+        assert!(!merkle_tree.verify_checked(&forged, &truncated));
+
+        let proof = merkle_tree.proof_for(LeafIndex(1)).unwrap();
+        assert!(merkle_tree.verify_checked(&transactions[1], &proof));
+    }
+
+    #[test]
+    fn a_merkle_tree_can_be_built_along_with_its_leaf_hashes() {
+        let transactions = vec!["A", "B", "C"];
+        let (mut merkle_tree, leaf_hashes) =
+            MerkleTree::new_with_leaf_hashes(transactions.clone()).unwrap();
+
+        assert_eq!(leaf_hashes, merkle_tree.leaf_hashes());
+        assert_eq!(leaf_hashes[1], MerkleTree::leaf_hash(&"B"));
+        assert_eq!(
+            merkle_tree.root(),
+            MerkleTree::new(transactions).unwrap().root()
+        );
+        let proof = merkle_tree.get_proof("C");
+        assert!(merkle_tree.verify("C", proof));
+    }
+
+    #[test]
+    fn a_failed_verification_explains_which_root_the_proof_folds_to() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        let other_tree = MerkleTree::new(vec!["A", "B", "D"]).unwrap();
+        let proof = other_tree.proof_for(LeafIndex(0)).unwrap();
+
+        assert_eq!(
+            MerkleTree::verify_explain(&"A", &proof, other_tree.root()),
+            VerifyOutcome::Valid
+        );
+        assert_eq!(
+            MerkleTree::verify_explain(&"A", &proof, merkle_tree.root()),
+            VerifyOutcome::ComputedRootMismatch {
+                computed: other_tree.root(),
+                expected: merkle_tree.root(),
+            }
+        );
+    }
+
+    #[test]
+    fn a_failed_verification_of_a_non_member_folds_to_no_known_root() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(0)).unwrap();
+
+        let VerifyOutcome::ComputedRootMismatch { computed, expected } =
+            MerkleTree::verify_explain(&"E", &proof, merkle_tree.root())
+        else {
+            panic!("a value that isn't a leaf verified");
+        };
+        assert_eq!(expected, merkle_tree.root());
+        assert_ne!(computed, merkle_tree.root());
+    }
+
+    #[test]
+    fn a_merkle_tree_can_add_a_leaf_streamed_in_chunks() {
+        let blob: Vec<u8> = (0..100_000).map(|byte| byte as u8).collect();
+        let mut merkle_tree = MerkleTree::<Vec<u8>>::from_leaf_hashes(vec![
+            MerkleTree::leaf_hash(&b"A".to_vec()),
+            MerkleTree::leaf_hash(&b"B".to_vec()),
+        ])
+        .unwrap();
+        merkle_tree.add_reader(Cursor::new(&blob)).unwrap();
+
+        assert_eq!(merkle_tree.leaf_hashes()[2], MerkleTree::leaf_hash(&blob));
+        let proof = merkle_tree.proof_for(LeafIndex(2)).unwrap();
+        assert!(merkle_tree.verify_checked(&blob, &proof));
+
+        let mut with_values = MerkleTree::new(vec![b"A".to_vec()]).unwrap();
+        assert_eq!(
+            with_values.add_reader(Cursor::new(&blob)),
+            Err(MerkleError::NoLeafValues)
+        );
+        assert_eq!(with_values.leaf_hashes().len(), 1);
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn a_merkle_tree_can_be_rehashed_with_another_hasher() {
+        use crate::hasher::Sha256MerkleHasher;
+
+        let transactions: Vec<u64> = (0..7).collect();
+        let merkle_tree = MerkleTree::new(transactions).unwrap();
+        let rehashed = merkle_tree.rehash::<Sha256MerkleHasher>().unwrap();
+
+        assert_ne!(rehashed.root(), merkle_tree.root());
+        assert_eq!(rehashed.get_leaf(LeafIndex(3)), Some(&3));
+        let proof = rehashed.detached_proof(LeafIndex(3)).unwrap();
+        assert_eq!(proof.algorithm, Sha256MerkleHasher::ALGORITHM);
+        assert_eq!(rehashed.verify_versioned(&3, &proof), Ok(true));
+        assert!(rehashed.verify_checked(&3, &proof.siblings));
+        assert!(!merkle_tree.verify_checked(&3, &proof.siblings));
+        assert_eq!(
+            merkle_tree.verify_versioned(&3, &proof),
+            Err(MerkleError::AlgorithmMismatch {
+                expected: DefaultMerkleHasher::ALGORITHM,
+                found: Sha256MerkleHasher::ALGORITHM,
+            })
+        );
+    }
+
+    #[test]
+    fn a_merkle_tree_doesnt_prove_a_value_hashing_like_an_internal_node() {
+        let transactions: Vec<Vec<u8>> = ["A", "B", "C", "D"]
+            .iter()
+            .map(|transaction| transaction.encode())
+            .collect();
+        let leaf_hashes: Vec<Hash> = transactions.iter().map(MerkleTree::leaf_hash).collect();
+        let forged = [leaf_hashes[0].to_be_bytes(), leaf_hashes[1].to_be_bytes()].concat();
+        assert_eq!(
+            MerkleTree::leaf_hash(&forged),
+            DefaultMerkleHasher::hash_pair(leaf_hashes[0], leaf_hashes[1])
+        );
+
+        for config in [
+            MerkleConfig::default(),
+            MerkleConfig::default().index_leaves(true),
+        ] {
+            let mut merkle_tree = MerkleTree::with_config(transactions.clone(), config).unwrap();
+            assert_eq!(merkle_tree.try_get_proof(&forged), Ok(Vec::new()));
+            assert_eq!(merkle_tree.get_proof_cached(&forged), Vec::new());
+            let proof = merkle_tree.get_proof(forged.clone());
+            assert!(!merkle_tree.verify(forged.clone(), proof));
+        }
+    }
+
+    #[test]
+    fn a_merkle_tree_can_verify_a_borrowed_proof_more_than_once() {
+        let transactions = vec![String::from("A"), String::from("B"), String::from("C")];
+        let merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
+        let other_tree = MerkleTree::new(vec![String::from("D")]).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(1)).unwrap();
+
+        assert!(merkle_tree.verify_ref(&transactions[1], &proof));
+        assert!(merkle_tree.verify_ref(&transactions[1], &proof));
+        assert!(!other_tree.verify_ref(&transactions[1], &proof));
+        assert!(!merkle_tree.verify_ref(&transactions[0], &proof));
+    }
+
+    #[test]
+    fn a_merkle_tree_cant_grow_past_its_leaf_limit() {
+        let config = MerkleConfig::default().max_leaves(3);
+        assert_eq!(
+            MerkleTree::with_config(vec!["A", "B", "C", "D"], config).err(),
+            Some(MerkleError::TooManyLeaves { limit: 3, got: 4 })
+        );
+
+        let mut merkle_tree = MerkleTree::with_config(vec!["A", "B"], config).unwrap();
+        assert_eq!(merkle_tree.add("C"), Ok(LeafIndex(2)));
+        let root = merkle_tree.root();
+        assert_eq!(
+            merkle_tree.add("D"),
+            Err(MerkleError::TooManyLeaves { limit: 3, got: 4 })
+        );
+        assert_eq!(
+            merkle_tree.insert_sorted("D"),
+            Err(MerkleError::TooManyLeaves { limit: 3, got: 4 })
+        );
+        assert_eq!(merkle_tree.root(), root);
+        assert_eq!(merkle_tree.leaves(), Some(&["A", "B", "C"][..]));
+    }
+
+    #[test]
+    fn a_prepared_verifier_agrees_with_verify() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        let verifier = merkle_tree.prepare_verifier(LeafIndex(3)).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(3)).unwrap();
+        for transaction in ["A", "D", "F"] {
+            assert_eq!(
+                verifier.verify(&transaction),
+                merkle_tree.verify_ref(&transaction, &proof)
+            );
+        }
+        assert!(verifier.verify(&"D"));
+        assert!(merkle_tree.prepare_verifier(LeafIndex(5)).is_none());
+
+        // The verifier borrows the tree, so it has to be prepared again after a mutation
+        merkle_tree.update(LeafIndex(3), "F").unwrap();
+        let verifier = merkle_tree.prepare_verifier(LeafIndex(3)).unwrap();
+        assert!(!verifier.verify(&"D"));
+        assert!(verifier.verify(&"F"));
+    }
+
+    #[test]
+    fn a_merkle_tree_reports_the_length_of_every_proof() {
+        let balanced = MerkleTree::new(vec!["A", "B", "C", "D"]).unwrap();
+        assert_eq!(balanced.proof_lengths(), [2, 2, 2, 2]);
+
+        // C is duplicated to give it a pair, which is still a sibling in its proof
+        let duplicated = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        assert_eq!(duplicated.proof_lengths(), [2, 2, 2]);
+
+        let config = MerkleConfig::default().odd_strategy(OddStrategy::Promote);
+        let promoted = MerkleTree::with_config(vec!["A", "B", "C"], config).unwrap();
+        assert_eq!(promoted.proof_lengths(), [2, 2, 1]);
+    }
+
+    #[test]
+    fn a_merkle_tree_built_from_sorted_leaves_can_prove_absence() {
+        // Sorting and deduplicating is up to the caller, nothing checks it in release builds
+        let merkle_tree = MerkleTree::from_sorted_unchecked(vec![10, 20, 30, 40, 50]).unwrap();
+
+        for absent in [5, 25, 55] {
+            let proof = merkle_tree.proof_of_absence(&absent).unwrap();
+            assert!(merkle_tree.verify_absence(&absent, &proof));
         }
+        assert!(merkle_tree.proof_of_absence(&40).is_none());
     }
-}
 
-impl<H: Hash + Clone> MerkleTree<H> {
-    pub fn new(transactions: Vec<H>) -> Result<Self, &'static str> {
-        Self::create_tree(transactions)
+    #[test]
+    fn a_merkle_tree_verifies_a_witness_only_at_its_index() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        let witness = merkle_tree.witness(LeafIndex(3)).unwrap();
+        assert_eq!(witness.leaf, "D");
+        assert!(merkle_tree.verify_witness(&witness));
+
+        let moved = Witness {
+            index: LeafIndex(2),
+            ..witness.clone()
+        };
+        assert!(!merkle_tree.verify_witness(&moved));
+        let past_the_end = Witness {
+            index: LeafIndex(9),
+            ..witness
+        };
+        assert!(!merkle_tree.verify_witness(&past_the_end));
+        assert!(merkle_tree.witness(LeafIndex(5)).is_none());
     }
 
-    // Fathers must have at least one son, if it does not have one, we clone the left one
-    fn create_parent_from_siblings(
-        left_son: Box<MerkleNode>,
-        mut right_son: Option<Box<MerkleNode>>,
-    ) -> MerkleNode {
-        let mut hasher = DefaultHasher::new();
-        left_son.hash_value.hash(&mut hasher);
+    #[test]
+    fn a_merkle_tree_reports_the_proofs_an_update_would_change() {
+        let impacted_proofs = |mut merkle_tree: MerkleTree<u32>, index: LeafIndex| {
+            let leaf_count = merkle_tree.leaf_hashes().len();
+            let before: Vec<_> = (0..leaf_count)
+                .map(|leaf| merkle_tree.proof_for(LeafIndex(leaf)))
+                .collect();
+            merkle_tree.update(index, 100).unwrap();
+            (0..leaf_count)
+                .filter(|&leaf| merkle_tree.proof_for(LeafIndex(leaf)) != before[leaf])
+                .map(LeafIndex)
+                .collect::<Vec<_>>()
+        };
 
-        match &right_son {
-            Some(right_sibling) => {
-                right_sibling.hash_value.hash(&mut hasher);
+        let merkle_tree = MerkleTree::new((0..8).collect()).unwrap();
+        let impact = merkle_tree.mutation_impact(LeafIndex(3)).unwrap();
+        assert_eq!(
+            impact,
+            ProofImpact {
+                invalidated: [0, 1, 2, 4, 5, 6, 7].map(LeafIndex).to_vec()
             }
-            None => {
-                right_son = Some(left_son.clone());
-                left_son.hash_value.hash(&mut hasher);
+        );
+        assert_eq!(
+            impact.invalidated,
+            impacted_proofs(merkle_tree, LeafIndex(3))
+        );
+
+        // The lone last leaf is its own sibling, so its proof changes too
+        let merkle_tree = MerkleTree::new((0..5).collect()).unwrap();
+        let impact = merkle_tree.mutation_impact(LeafIndex(4)).unwrap();
+        assert_eq!(impact.invalidated, [0, 1, 2, 3, 4].map(LeafIndex));
+        assert_eq!(
+            impact.invalidated,
+            impacted_proofs(merkle_tree, LeafIndex(4))
+        );
+
+        let config = MerkleConfig::default()
+            .arity(3)
+            .odd_strategy(OddStrategy::ZeroPad);
+        let merkle_tree = MerkleTree::with_config((0..7).collect(), config).unwrap();
+        let impact = merkle_tree.mutation_impact(LeafIndex(6)).unwrap();
+        assert_eq!(
+            impact.invalidated,
+            impacted_proofs(merkle_tree, LeafIndex(6))
+        );
+    }
+
+    #[cfg(feature = "sha2-direct-compress")]
+    #[test]
+    fn a_large_merkle_tree_has_the_same_root_with_direct_compression() {
+        use crate::hasher::Sha256MerkleHasher;
+
+        type Sha256Tree = MerkleTree<u32, Sha256MerkleHasher>;
+        // The general path hashes through the hasher, the binary one compresses every pair directly
+        let config = MerkleConfig::default();
+        for size in [1 << 14, (1 << 14) + 3] {
+            let transactions: Vec<u32> = (0..size as u32).collect();
+            let leaves = || Sha256Tree::get_hashes_of_transactions(&transactions, &config);
+
+            let scalar = Sha256Tree::create_levels(leaves(), size, &config).unwrap();
+            let compressed = Sha256Tree::create_levels_from_pairs(leaves(), size, &config).unwrap();
+            assert_eq!(scalar, compressed);
+            let merkle_tree = Sha256Tree::with_hasher(transactions, config).unwrap();
+            assert_eq!(merkle_tree.root(), scalar[scalar.len() - 1][0]);
+        }
+    }
+
+    #[test]
+    fn a_set_of_leaves_verifies_against_its_root_in_any_order() {
+        let root = MerkleTree::new(vec!["A", "B", "B", "C"]).unwrap().root();
+
+        assert!(MerkleTree::verify_set(&["C", "B", "A", "B"], root));
+        assert!(MerkleTree::verify_set(&["B", "A", "C", "B"], root));
+        assert!(!MerkleTree::verify_set(&["A", "B", "C"], root));
+    }
+
+    #[test]
+    fn a_parent_cant_be_made_from_no_sons() {
+        let config = MerkleConfig::default();
+
+        assert_eq!(
+            config.create_parent::<DefaultMerkleHasher>(&[]),
+            Err(MerkleError::Internal("a parent can't be made from no sons"))
+        );
+        let son = Hash::from(1);
+        assert_eq!(
+            config.create_parent::<DefaultMerkleHasher>(&[son]),
+            Ok(DefaultMerkleHasher::hash_pair(son, son))
+        );
+    }
+
+    #[test]
+    fn a_merkle_tree_can_remove_every_copy_of_a_transaction() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B", "A", "C"]).unwrap();
+
+        assert_eq!(merkle_tree.remove_all(&"A"), Ok(2));
+        assert_eq!(merkle_tree.leaves(), Some(&["B", "C"][..]));
+        assert_eq!(
+            merkle_tree.root(),
+            MerkleTree::new(vec!["B", "C"]).unwrap().root()
+        );
+        let proof = merkle_tree.get_proof("C");
+        assert!(merkle_tree.verify("C", proof));
+        assert_eq!(merkle_tree.remove_all(&"A"), Ok(0));
+
+        let mut merkle_tree = MerkleTree::new(vec!["A", "A"]).unwrap();
+        let root = merkle_tree.root();
+        assert_eq!(
+            merkle_tree.remove_all(&"A"),
+            Err(MerkleError::EmptyTransactions)
+        );
+        assert_eq!(merkle_tree.root(), root);
+    }
+
+    #[test]
+    fn a_merkle_tree_knows_its_root_after_an_add_without_adding() {
+        let mut merkle_tree = MerkleTree::with_salt(vec!["A", "B", "C", "D"], 7).unwrap();
+        let root = merkle_tree.root();
+
+        let prospective = merkle_tree.root_if_added(&"E").unwrap();
+        assert_eq!(merkle_tree.root(), root);
+        assert_eq!(merkle_tree.leaf_hashes().len(), 4);
+        merkle_tree.add("E").unwrap();
+        assert_eq!(merkle_tree.root(), prospective);
+
+        for config in [
+            MerkleConfig::default(),
+            MerkleConfig::default().arity(3),
+            MerkleConfig::default().odd_strategy(OddStrategy::Promote),
+        ] {
+            let config = config.allow_empty(true);
+            let mut merkle_tree = MerkleTree::with_config(Vec::new(), config).unwrap();
+            for transaction in 0..10 {
+                let prospective = merkle_tree.root_if_added(&transaction);
+                merkle_tree.add(transaction).unwrap();
+                assert_eq!(prospective, Ok(merkle_tree.root()));
             }
         }
 
-        MerkleNode::new(hasher.finish(), Some(left_son), right_son)
+        // A dry run fails like the add would
+        let config = MerkleConfig::default().max_leaves(2);
+        let mut merkle_tree = MerkleTree::with_config(vec!["A", "B"], config).unwrap();
+        assert_eq!(
+            merkle_tree.root_if_added(&"C"),
+            Err(MerkleError::TooManyLeaves { limit: 2, got: 3 })
+        );
+        assert!(merkle_tree.add("C").is_err());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn a_proof_can_still_be_written_with_the_old_sibling_name() {
+        use crate::merkle_tree::SiblingsHash;
+
+        let merkle_tree = MerkleTree::new(vec!["A", "B"]).unwrap();
+        let proof: Vec<SiblingsHash> = vec![SiblingsHash::Right(MerkleTree::leaf_hash(&"B"))];
+
+        assert_eq!(proof, merkle_tree.proof_for(LeafIndex(0)).unwrap());
+        assert!(merkle_tree.verify_ref(&"A", &proof));
     }
 
-    fn create_tree(transactions: Vec<H>) -> Result<MerkleTree<H>, &'static str> {
-        if transactions.is_empty() {
-            return Err("Can't create a tree without elements");
+    #[test]
+    fn a_merkle_tree_can_be_built_along_with_the_proof_of_every_leaf() {
+        let transactions = vec!["A", "B", "C", "D", "E"];
+        let (root, proofs) = MerkleTree::build_with_all_proofs(transactions.clone()).unwrap();
+
+        assert_eq!(proofs.len(), transactions.len());
+        for (transaction, proof) in transactions.iter().zip(&proofs) {
+            assert!(verify_proof(
+                MerkleTree::leaf_hash(transaction),
+                proof,
+                root
+            ));
         }
+    }
 
-        let transactions_hash = Self::get_hashes_of_transactions(&transactions);
-        let mut nodes: Vec<Box<MerkleNode>> = transactions_hash
-            .into_iter()
-            .map(|hash| Box::new(MerkleNode::new(hash, None, None)))
-            .collect();
+    #[test]
+    fn a_proof_is_rejected_against_a_tree_smaller_than_required() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        let tree_head = merkle_tree.tree_head();
+        let proof = merkle_tree.proof_for(LeafIndex(1)).unwrap();
+        let verify = |min_size| {
+            MerkleTree::verify_with_min_size(&"B", LeafIndex(1), &proof, &tree_head, min_size)
+        };
 
-        // We loop all the elements and construct the next level of the tree, we stop once there is only one element (the root)
-        while nodes.len() > 1 {
-            let mut parents = Vec::new();
-            let mut iter = nodes.into_iter();
+        assert_eq!(verify(3), Ok(true));
+        assert_eq!(verify(4), Ok(false));
 
-            while let (Some(left_son), right_son) = (iter.next(), iter.next()) {
-                let parent = Self::create_parent_from_siblings(left_son, right_son);
-                parents.push(Box::new(parent));
-            }
+        // A single leaf is its own root, it can't pass for a larger tree with an empty proof
+        let trivial = TreeHead::new(MerkleTree::leaf_hash(&"B"), 3);
+        assert_eq!(
+            MerkleTree::verify_with_min_size(&"B", LeafIndex(0), &[], &trivial, 2),
+            Err(MerkleError::WrongDepth {
+                expected: 2,
+                got: 0
+            })
+        );
+    }
 
-            nodes = parents;
+    #[test]
+    fn the_root_of_a_merkle_tree_is_proven_by_an_empty_proof() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D"]).unwrap();
+        let root = merkle_tree.root();
+        let leaf_hash = MerkleTree::leaf_hash(&"A");
+
+        assert!(merkle_tree.is_root(root));
+        assert!(merkle_tree.verify_hash(root, &[]));
+        assert!(!merkle_tree.is_root(leaf_hash));
+        assert!(!merkle_tree.verify_hash(leaf_hash, &[]));
+        assert!(merkle_tree.verify_hash(leaf_hash, &merkle_tree.proof_for(LeafIndex(0)).unwrap()));
+    }
+
+    #[test]
+    fn a_merkle_tree_can_place_its_leaves_in_an_external_order() {
+        let transactions = vec!["A", "B", "C", "D"];
+        let merkle_tree =
+            MerkleTree::new_with_order(transactions.clone(), vec![2, 0, 3, 1]).unwrap();
+
+        let external = MerkleTree::new(vec!["C", "A", "D", "B"]).unwrap();
+        assert_eq!(merkle_tree.root(), external.root());
+        assert_eq!(merkle_tree.leaves(), Some(&transactions[..]));
+        assert_eq!(merkle_tree.get_leaf(LeafIndex(0)), Some(&"C"));
+        let proof = merkle_tree.proof_for(LeafIndex(0)).unwrap();
+        assert!(merkle_tree.verify_ref(&"C", &proof));
+
+        // Mutations keep the caller's order apart from the tree's
+        let mut merkle_tree = merkle_tree;
+        merkle_tree.add("E").unwrap();
+        merkle_tree.update(LeafIndex(1), "Z").unwrap();
+        merkle_tree.remove_all(&"D").unwrap();
+        assert_eq!(merkle_tree.leaves(), Some(&["Z", "B", "C", "E"][..]));
+        let external = MerkleTree::new(vec!["C", "Z", "B", "E"]).unwrap();
+        assert_eq!(merkle_tree.root(), external.root());
+        assert_eq!(merkle_tree.get_leaf(LeafIndex(2)), Some(&"B"));
+
+        for order in [vec![0, 1, 2], vec![0, 0, 1, 2], vec![0, 1, 2, 4]] {
+            assert!(matches!(
+                MerkleTree::new_with_order(transactions.clone(), order),
+                Err(MerkleError::BadOrder)
+            ));
         }
+    }
 
-        Ok(Self {
-            merkle_root: *nodes[0].clone(),
-            leafs: transactions,
-        })
+    #[test]
+    fn a_proof_tampered_at_its_first_level_is_rejected_there() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E", "F", "G", "H"]).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(5)).unwrap();
+        assert!(merkle_tree.proof_matches_path(LeafIndex(5), &proof));
+        assert!(!merkle_tree.proof_matches_path(LeafIndex(4), &proof));
+
+        let mut tampered = proof.clone();
+        tampered[0] = SiblingHash::Left(Hash::from(7));
+        assert!(!merkle_tree.proof_matches_path(LeafIndex(5), &tampered));
+        assert_eq!(
+            merkle_tree.mismatched_level(LeafIndex(5), &tampered),
+            Some(0)
+        );
+        assert_eq!(
+            merkle_tree.mismatched_level(LeafIndex(5), &proof[..2]),
+            Some(2)
+        );
     }
 
-    fn get_hashes_of_transactions(transactions: &[H]) -> Vec<u64> {
-        transactions
-            .iter()
-            .map(|transaction| {
-                let mut hasher = DefaultHasher::new();
-                transaction.hash(&mut hasher);
-                hasher.finish()
+    #[test]
+    fn a_merkle_tree_returns_the_index_of_every_added_leaf() {
+        let mut merkle_tree = MerkleTree::allow_empty(Vec::new()).unwrap();
+
+        for (position, transaction) in ["A", "B", "C", "D", "E"].into_iter().enumerate() {
+            let index = merkle_tree.add(transaction).unwrap();
+            assert_eq!(index, LeafIndex(position));
+            assert_eq!(merkle_tree.get_leaf(index), Some(&transaction));
+            let proof = merkle_tree.proof_for(index).unwrap();
+            assert!(merkle_tree.verify_ref(&transaction, &proof));
+        }
+    }
+
+    #[test]
+    fn a_merkle_tree_can_verify_against_a_root_given_in_any_radix() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(1)).unwrap();
+        let root = u64::from(merkle_tree.root());
+
+        let decimal = root.to_string();
+        let hex = format!("{root:x}");
+        assert_eq!(
+            MerkleTree::verify_root_str("B", &proof, &decimal, 10),
+            Ok(true)
+        );
+        assert_eq!(MerkleTree::verify_root_str("B", &proof, &hex, 16), Ok(true));
+        assert_eq!(
+            MerkleTree::verify_root_str("A", &proof, &hex, 16),
+            Ok(false)
+        );
+
+        assert_eq!(
+            MerkleTree::verify_root_str("B", &proof, &hex, 10),
+            Err(MerkleError::BadRoot {
+                root: hex.clone(),
+                radix: 10
             })
-            .collect()
+        );
+        assert!(MerkleTree::verify_root_str("B", &proof, &decimal, 40).is_err());
     }
 
-    pub fn verify(&mut self, transaction: H, proof: Vec<SiblingHash>) -> bool {
-        let mut hasher = DefaultHasher::new();
-        transaction.hash(&mut hasher);
-        let mut transaction = hasher.finish();
-        for sibling_hash in proof {
-            hasher = DefaultHasher::new();
-            match sibling_hash {
-                SiblingHash::Left(left_hash) => {
-                    left_hash.hash(&mut hasher);
-                    transaction.hash(&mut hasher);
-                }
-                SiblingHash::Right(right_hash) => {
-                    transaction.hash(&mut hasher);
-                    right_hash.hash(&mut hasher);
-                }
-            }
+    #[test]
+    fn a_proof_tells_which_of_several_roots_it_was_issued_for() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        let first = merkle_tree.root();
+        merkle_tree.add("D").unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(1)).unwrap();
+        let second = merkle_tree.root();
+        merkle_tree.add("E").unwrap();
+        let roots = [first, second, merkle_tree.root()];
+
+        assert_eq!(MerkleTree::matching_root("B", &proof, &roots), Some(1));
+        assert_eq!(MerkleTree::matching_root("A", &proof, &roots), None);
+    }
+
+    #[test]
+    fn a_promoted_chain_of_lone_nodes_is_collapsed_into_one_edge() {
+        let config = MerkleConfig::default().odd_strategy(OddStrategy::Promote);
+        let merkle_tree = MerkleTree::with_config(vec!["A", "B", "C", "D", "E"], config).unwrap();
+
+        // E goes up two levels alone, and only meets a sibling under the root
+        let four = MerkleTree::new(vec!["A", "B", "C", "D"]).unwrap();
+        let leaf_hash = MerkleTree::leaf_hash(&"E");
+        assert_eq!(merkle_tree.proof_lengths(), [3, 3, 3, 3, 1]);
+        assert_eq!(
+            merkle_tree.proof_for(LeafIndex(4)).unwrap(),
+            [SiblingHash::Left(four.root())]
+        );
+        assert_eq!(
+            merkle_tree.root(),
+            DefaultMerkleHasher::hash_pair(four.root(), leaf_hash)
+        );
+    }
+
+    #[test]
+    fn sorted_merkle_trees_can_be_merged_keeping_their_order() {
+        let first = MerkleTree::from_sorted_unchecked(vec!["A", "C", "E"]).unwrap();
+        let second = MerkleTree::from_sorted_unchecked(vec!["B", "D", "F"]).unwrap();
 
-            transaction = hasher.finish();
+        let merged = first.merge_sorted(second).unwrap();
+        assert_eq!(merged.leaves(), Some(&["A", "B", "C", "D", "E", "F"][..]));
+        assert_eq!(
+            merged.root(),
+            MerkleTree::new(vec!["A", "B", "C", "D", "E", "F"])
+                .unwrap()
+                .root()
+        );
+        for absent in ["AA", "CC", "G"] {
+            let proof = merged.proof_of_absence(&absent).unwrap();
+            assert!(merged.verify_absence(&absent, &proof));
         }
 
-        transaction == self.merkle_root.hash_value
+        let mut pruned = MerkleTree::from_sorted_unchecked(vec!["B"]).unwrap();
+        pruned.prune_leaves();
+        assert!(matches!(
+            merged.merge_sorted(pruned),
+            Err(MerkleError::NoLeafValues)
+        ));
     }
 
-    fn recursive_get_proof(
-        current_node: &MerkleNode,
-        proof: &mut Vec<SiblingHash>,
-        transaction_hash: u64,
-    ) -> bool {
-        let mut added_proof = false;
-        current_node.left_son.as_ref().inspect(|left| {
-            if left.hash_value == transaction_hash {
-                current_node.right_son.as_ref().inspect(|right_sibling| {
-                    proof.push(SiblingHash::Right(right_sibling.hash_value))
-                });
-                added_proof = true;
-            }
-            if Self::recursive_get_proof(left, proof, transaction_hash) {
-                current_node.right_son.as_ref().inspect(|right_sibling| {
-                    proof.push(SiblingHash::Right(right_sibling.hash_value))
-                });
-                added_proof = true;
+    #[test]
+    fn a_proof_longer_than_its_leaf_is_deep_is_rejected() {
+        let config = MerkleConfig::default().odd_strategy(OddStrategy::Promote);
+        let merkle_tree = MerkleTree::with_config(vec!["A", "B", "C", "D", "E"], config).unwrap();
+
+        // E is promoted up to the root, so its proof is shorter than the others
+        let mut proof = merkle_tree.proof_for(LeafIndex(4)).unwrap();
+        assert_eq!(merkle_tree.verify_at(&"E", LeafIndex(4), &proof), Ok(true));
+        proof.push(SiblingHash::Right(Hash::from(7)));
+        assert_eq!(
+            merkle_tree.verify_at(&"E", LeafIndex(4), &proof),
+            Err(MerkleError::WrongDepth {
+                expected: 1,
+                got: 2
+            })
+        );
+
+        for (arity, odd_strategy) in [
+            (2, OddStrategy::Duplicate),
+            (3, OddStrategy::ZeroPad),
+            (3, OddStrategy::Promote),
+            (4, OddStrategy::Promote),
+        ] {
+            let config = MerkleConfig::default()
+                .arity(arity)
+                .odd_strategy(odd_strategy);
+            for size in 1..20 {
+                let merkle_tree = MerkleTree::with_config((0..size).collect(), config).unwrap();
+                let depths: Vec<usize> = (0..size)
+                    .map(|index| config.proof_length(size, index))
+                    .collect();
+                assert_eq!(depths, merkle_tree.proof_lengths());
             }
-        });
+        }
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn a_canonical_merkle_tree_matches_its_test_vector() {
+        let merkle_tree = MerkleTree::new_canonical(vec!["a", "b", "c"]).unwrap();
+
+        assert_eq!(
+            merkle_tree.leaf_hashes()[0],
+            Hash::from(0x022a_6979_e6da_b7aa)
+        );
+        assert_eq!(merkle_tree.root(), Hash::from(0x570e_9800_96fc_fce1));
+        assert_eq!(merkle_tree.root().to_string(), "570e980096fcfce1");
+
+        // Integers are encoded big endian, so this root is the same in every language
+        let transactions: Vec<u32> = vec![1000, 1500, 2000, 3000, 4000, 5500, 7000, 8700];
+        let merkle_tree = MerkleTree::new_canonical(transactions).unwrap();
+        assert_eq!(merkle_tree.root(), Hash::from(0x1867_e377_4c04_fa82));
+    }
+
+    #[test]
+    fn a_merkle_tree_gives_the_path_from_the_root_to_a_leaf() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E", "F"]).unwrap();
+
+        // F is right under the root, then left, then right
+        let (path, _) = merkle_tree.get_proof_with_path(LeafIndex(5)).unwrap();
+        assert_eq!(path, 0b101);
+        for index in 0..6 {
+            let (path, proof) = merkle_tree.get_proof_with_path(LeafIndex(index)).unwrap();
+            let depth = proof.len();
+            let from_path = (0..depth).fold(0, |index, step| {
+                index | ((path >> step & 1) as usize) << (depth - 1 - step)
+            });
+            assert_eq!(from_path, index);
+            assert_eq!(proof_index(&proof), Some(index));
+        }
+
+        let config = MerkleConfig::default().odd_strategy(OddStrategy::Promote);
+        let promoted = MerkleTree::with_config(vec!["A", "B", "C"], config).unwrap();
+        assert_eq!(promoted.get_proof_with_path(LeafIndex(0)), None);
+    }
+
+    #[test]
+    fn a_merkle_tree_can_verify_a_leaf_given_as_bytes() {
+        let leaves: Vec<Vec<u8>> = vec![b"A".to_vec(), b"B".to_vec(), b"C".to_vec()];
+        let merkle_tree = MerkleTree::with_salt(leaves.clone(), 7).unwrap();
+
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = merkle_tree.proof_for(LeafIndex(index)).unwrap();
+            assert!(merkle_tree.verify_bytes(leaf, &proof));
+            assert!(!merkle_tree.verify_bytes(b"D", &proof));
+        }
+
+        // Any leaf type is verified through its encoding
+        let merkle_tree = MerkleTree::new(vec![1u32, 2, 3]).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(1)).unwrap();
+        assert!(merkle_tree.verify_bytes(&2u32.encode(), &proof));
+        assert!(!merkle_tree.verify_bytes(&2u64.encode(), &proof));
+    }
+
+    #[test]
+    fn a_quorum_proof_holds_only_while_enough_leaves_verify() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        let indices = [LeafIndex(0), LeafIndex(2), LeafIndex(4)];
+        let mut proof = merkle_tree.quorum_proof(&indices).unwrap();
+        assert!(merkle_tree.verify_quorum(&proof, 3));
+
+        proof.leaves[1].1 = MerkleTree::leaf_hash(&"X");
+        assert!(!merkle_tree.verify_quorum(&proof, 3));
+        assert!(merkle_tree.verify_quorum(&proof, 2));
+
+        // Claiming the same leaf again doesn't add to the count
+        let twice = merkle_tree
+            .quorum_proof(&[LeafIndex(0), LeafIndex(0)])
+            .unwrap();
+        assert!(!merkle_tree.verify_quorum(&twice, 2));
+        assert!(merkle_tree.quorum_proof(&[LeafIndex(5)]).is_none());
+    }
+
+    #[test]
+    fn a_merkle_tree_finds_the_common_ancestor_of_two_leaves() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D"]).unwrap();
+        let left = DefaultMerkleHasher::hash_pair(
+            MerkleTree::leaf_hash(&"A"),
+            MerkleTree::leaf_hash(&"B"),
+        );
+
+        assert_eq!(
+            merkle_tree.common_ancestor(LeafIndex(0), LeafIndex(1)),
+            Some(left)
+        );
+        assert_eq!(
+            merkle_tree.common_ancestor(LeafIndex(0), LeafIndex(3)),
+            Some(merkle_tree.root())
+        );
+        assert_eq!(
+            merkle_tree.common_ancestor(LeafIndex(2), LeafIndex(2)),
+            Some(MerkleTree::leaf_hash(&"C"))
+        );
+        assert_eq!(
+            merkle_tree.common_ancestor(LeafIndex(0), LeafIndex(4)),
+            None
+        );
+    }
+
+    #[test]
+    fn a_merkle_tree_warns_about_leaf_counts_that_need_padding() {
+        let (merkle_tree, warnings) =
+            MerkleTree::new_with_warnings(vec!["A", "B", "C", "D", "E"]).unwrap();
+
+        // E is duplicated, and so is its parent a level up
+        assert_eq!(
+            warnings,
+            [BuildWarning::Padding {
+                leaves: 5,
+                padded_nodes: 2,
+                suggested_leaves: 8
+            }]
+        );
+        assert_eq!(
+            merkle_tree.root(),
+            MerkleTree::new(vec!["A", "B", "C", "D", "E"])
+                .unwrap()
+                .root()
+        );
+
+        let (_, warnings) = MerkleTree::new_with_warnings(vec!["A", "B", "C", "D"]).unwrap();
+        assert_eq!(warnings, []);
+    }
 
-        current_node.right_son.as_ref().inspect(|right| {
-            if right.hash_value == transaction_hash {
-                current_node
-                    .left_son
-                    .as_ref()
-                    .inspect(|left_sibling| proof.push(SiblingHash::Left(left_sibling.hash_value)));
-                added_proof = true;
-            }
-            if Self::recursive_get_proof(right, proof, transaction_hash) {
-                current_node
-                    .right_son
-                    .as_ref()
-                    .inspect(|left_sibling| proof.push(SiblingHash::Left(left_sibling.hash_value)));
-                added_proof = true;
+    #[test]
+    fn a_merkle_tree_root_matches_one_folded_by_hand_for_every_size() {
+        for size in 1..256u32 {
+            let mut level: Vec<Hash> = (0..size).map(|leaf| MerkleTree::leaf_hash(&leaf)).collect();
+            while level.len() > 1 {
+                level = level
+                    .chunks(2)
+                    .map(|pair| DefaultMerkleHasher::hash_pair(pair[0], pair[pair.len() - 1]))
+                    .collect();
             }
-        });
 
-        added_proof
+            let merkle_tree = MerkleTree::new((0..size).collect()).unwrap();
+            assert_eq!(merkle_tree.root(), level[0], "size {size}");
+        }
     }
 
-    pub fn get_proof(&mut self, transaction: H) -> Vec<SiblingHash> {
-        let mut proof = Vec::new();
-        let mut hasher = DefaultHasher::new();
-        transaction.hash(&mut hasher);
-        Self::recursive_get_proof(&self.merkle_root, &mut proof, hasher.finish());
-        proof
+    #[test]
+    fn a_merkle_tree_detects_a_node_that_doesnt_match_its_sons() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        assert_eq!(merkle_tree.validate_structure(), Ok(()));
+        let config = MerkleConfig::default()
+            .arity(3)
+            .odd_strategy(OddStrategy::Promote);
+        let promoted = MerkleTree::with_config(vec!["A", "B", "C", "D", "E"], config).unwrap();
+        assert_eq!(promoted.validate_structure(), Ok(()));
+
+        let Some(Ok(levels)) = merkle_tree.levels.get_mut() else {
+            panic!("the tree was built to be validated");
+        };
+        let stored = Hash::from(7);
+        Arc::make_mut(levels)[1][1] = stored;
+        assert_eq!(
+            merkle_tree.validate_structure(),
+            Err(StructuralError::NodeMismatch {
+                level: 1,
+                index: 1,
+                stored,
+                computed: DefaultMerkleHasher::hash_pair(
+                    MerkleTree::leaf_hash(&"C"),
+                    MerkleTree::leaf_hash(&"D")
+                ),
+            })
+        );
+
+        let Some(Ok(levels)) = merkle_tree.levels.get_mut() else {
+            panic!("the tree was built to be validated");
+        };
+        Arc::make_mut(levels)[1].pop();
+        assert!(matches!(
+            merkle_tree.validate_structure(),
+            Err(StructuralError::LevelLength { level: 1, .. })
+        ));
     }
 
-    pub fn add(&mut self, transaction: H) -> Result<(), &'static str> {
-        self.leafs.push(transaction);
-        self.merkle_root = Self::create_tree(self.leafs.clone())?.merkle_root;
-        Ok(())
+    #[test]
+    fn a_quorum_proof_is_the_same_for_any_order_of_its_leaves() {
+        let merkle_tree = MerkleTree::new((0..12u32).collect()).unwrap();
+        let proof = merkle_tree
+            .quorum_proof(&[LeafIndex(5), LeafIndex(2), LeafIndex(9)])
+            .unwrap();
+        let sorted = merkle_tree
+            .quorum_proof(&[LeafIndex(2), LeafIndex(5), LeafIndex(9), LeafIndex(5)])
+            .unwrap();
+
+        assert_eq!(proof, sorted);
+        let indices: Vec<LeafIndex> = proof.leaves.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, [LeafIndex(2), LeafIndex(5), LeafIndex(9)]);
+        assert!(merkle_tree.verify_quorum(&proof, 3));
+        assert!(merkle_tree.verify_quorum(&sorted, 3));
     }
-}
 
-#[cfg(test)]
-pub mod test {
+    #[test]
+    fn a_leaf_hash_is_verified_bound_to_its_index_and_the_tree_size() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        let root = merkle_tree.root();
+        let leaf_hash = MerkleTree::leaf_hash(&"C");
+        let proof = merkle_tree.proof_for(LeafIndex(2)).unwrap();
+        let verify = |index, proof: &[SiblingHash], root, size| {
+            MerkleTree::<&str>::verify_leaf_hash_at(leaf_hash, LeafIndex(index), proof, root, size)
+        };
+        assert_eq!(verify(2, &proof, root, 5), Ok(true));
 
-    use crate::merkle_tree::MerkleTree;
+        assert_eq!(
+            verify(5, &proof, root, 5),
+            Err(MerkleError::IndexOutOfBounds(5))
+        );
+        assert_eq!(
+            verify(2, &proof[..2], root, 5),
+            Err(MerkleError::WrongDepth {
+                expected: 3,
+                got: 2
+            })
+        );
+        assert_eq!(verify(3, &proof, root, 5), Ok(false));
+        assert_eq!(verify(2, &proof, Hash::from(7), 5), Ok(false));
+    }
 
     #[test]
-    fn cant_create_a_merkle_tree_without_transactions() {
-        let transactions: Vec<String> = Vec::new();
-        let merkle_tree = MerkleTree::new(transactions);
+    fn a_merkle_tree_is_a_prefix_of_the_trees_it_was_appended_to() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B"]).unwrap();
 
-        assert!(merkle_tree.is_err());
+        assert!(merkle_tree.is_prefix_of(&MerkleTree::new(vec!["A", "B", "C"]).unwrap()));
+        assert!(merkle_tree.is_prefix_of(&merkle_tree));
+        assert!(!merkle_tree.is_prefix_of(&MerkleTree::new(vec!["A", "C", "B"]).unwrap()));
+        assert!(!merkle_tree.is_prefix_of(&MerkleTree::new(vec!["A"]).unwrap()));
+
+        let mut pruned = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        pruned.prune_leaves();
+        assert!(merkle_tree.is_prefix_of(&pruned));
     }
 
     #[test]
-    fn a_merkle_tree_can_contain_one_transaction() {
-        let transactions = vec![String::from("A")];
-        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
-        let transaction = transactions[0].clone();
-        let proof = merkle_tree.get_proof(transaction.clone());
+    fn a_snapshot_keeps_serving_proofs_against_the_root_it_was_taken_at() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D"]).unwrap();
+        let snapshot = merkle_tree.snapshot().unwrap();
+        let old_root = merkle_tree.root();
+        for transaction in ["E", "F", "G", "H"] {
+            merkle_tree.add(transaction).unwrap();
+        }
 
-        assert!(merkle_tree.verify(transaction, proof))
+        assert_ne!(merkle_tree.root(), old_root);
+        assert_eq!(snapshot.root(), old_root);
+        assert_eq!(snapshot.len(), 4);
+        for (index, transaction) in ["A", "B", "C", "D"].iter().enumerate() {
+            let proof = snapshot.proof(LeafIndex(index)).unwrap();
+            assert!(snapshot.verify(transaction, &proof));
+            assert_eq!(
+                MerkleTree::<&str>::fold_transaction(transaction, &proof),
+                old_root
+            );
+            assert!(!merkle_tree.verify_iter(*transaction, proof));
+        }
+        assert_eq!(snapshot.proof(LeafIndex(4)), None);
+        assert!(!snapshot.verify(&"E", &merkle_tree.proof_for(LeafIndex(4)).unwrap()));
     }
 
     #[test]
-    fn a_merkle_tree_can_contain_one_level_of_transactions() {
-        let transactions = vec![String::from("A"), String::from("B")];
-        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
-        let transaction = transactions[0].clone();
-        let proof = merkle_tree.get_proof(transaction.clone());
+    fn a_merkle_tree_gives_the_leaf_hash_expected_at_an_index() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        let leaves = merkle_tree.leaves().unwrap();
 
-        assert!(merkle_tree.verify(transaction, proof));
+        for (index, leaf) in leaves.iter().enumerate() {
+            let leaf_hash = merkle_tree.expected_leaf_for(LeafIndex(index));
+            assert_eq!(leaf_hash, Some(MerkleTree::leaf_hash(leaf)));
+            let proof = merkle_tree.proof_for(LeafIndex(index)).unwrap();
+            assert_eq!(fold_proof(leaf_hash.unwrap(), &proof), merkle_tree.root());
+        }
+        assert_eq!(merkle_tree.expected_leaf_for(LeafIndex(5)), None);
     }
+
     #[test]
-    fn a_merkle_tree_can_contain_two_level_of_transactions() {
-        let transactions = vec![
-            String::from("A"),
-            String::from("B"),
-            String::from("C"),
-            String::from("D"),
-        ];
-        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
-        let transaction = transactions[0].clone();
-        let proof = merkle_tree.get_proof(transaction.clone());
+    fn permuted_merkle_trees_have_the_same_sorted_root() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        let permuted = MerkleTree::new(vec!["C", "A", "B"]).unwrap();
+        let other = MerkleTree::new(vec!["C", "A", "D"]).unwrap();
 
-        assert!(merkle_tree.verify(transaction, proof));
+        assert_ne!(merkle_tree.root(), permuted.root());
+        let sorted_root = merkle_tree.sorted_root();
+        assert_eq!(permuted.sorted_root(), sorted_root);
+        assert_ne!(other.sorted_root(), sorted_root);
+        assert_eq!(merkle_tree.try_sorted_root(), Ok(sorted_root));
+
+        // The same canonical order as sets and sorted trees
+        assert!(MerkleTree::verify_set(&["B", "C", "A"], sorted_root));
+        let sorted = MerkleTree::from_sorted_unchecked(vec!["A", "B", "C"]).unwrap();
+        assert_eq!(sorted.root(), sorted_root);
+
+        let mut pruned = permuted;
+        pruned.prune_leaves();
+        assert_eq!(pruned.try_sorted_root(), Err(MerkleError::NoLeafValues));
     }
 
     #[test]
-    fn a_merkle_tree_can_contain_an_odd_number_of_transactions() {
-        let transactions = vec![String::from("A"), String::from("B"), String::from("C")];
-        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
-        let transaction = transactions[0].clone();
-        let proof = merkle_tree.get_proof(transaction.clone());
+    fn a_merkle_tree_commits_staged_mutations_with_one_rebuild() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        merkle_tree.begin();
+        merkle_tree.add("D").unwrap();
+        merkle_tree.add("E").unwrap();
+        merkle_tree.update(LeafIndex(0), "Z").unwrap();
+        merkle_tree.remove_all(&"B").unwrap();
+        assert_eq!(merkle_tree.rebuilds.load(Ordering::Relaxed), 0);
 
-        assert!(merkle_tree.verify(transaction, proof));
+        assert_eq!(merkle_tree.commit(), Ok(()));
+        assert_eq!(merkle_tree.rebuilds.load(Ordering::Relaxed), 1);
+        let expected = MerkleTree::new(vec!["Z", "C", "D", "E"]).unwrap();
+        assert_eq!(merkle_tree.root(), expected.root());
+        assert_eq!(merkle_tree.leaves(), expected.leaves());
+
+        // Leaves that can't be built are rolled back on commit
+        merkle_tree.begin();
+        merkle_tree.leaves_mut().clear();
+        assert_eq!(merkle_tree.commit(), Err(MerkleError::EmptyTransactions));
+        assert_eq!(merkle_tree.root(), expected.root());
     }
+
     #[test]
-    fn a_merkle_tree_can_contain_multiple_levels_of_transactions() {
-        let transactions = vec![
-            String::from("A"),
-            String::from("B"),
-            String::from("C"),
-            String::from("D"),
-            String::from("E"),
-            String::from("F"),
-        ];
-        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
-        let transaction = transactions[0].clone();
-        let proof = merkle_tree.get_proof(transaction.clone());
+    fn a_merkle_tree_rolls_back_staged_mutations() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        let root = merkle_tree.root();
+        merkle_tree.begin();
+        merkle_tree.add("D").unwrap();
+        merkle_tree.update(LeafIndex(1), "Z").unwrap();
+        assert_ne!(merkle_tree.root(), root);
 
-        assert!(merkle_tree.verify(transaction, proof));
+        merkle_tree.rollback();
+        assert_eq!(merkle_tree.root(), root);
+        assert_eq!(merkle_tree.leaves(), Some(&["A", "B", "C"][..]));
+        assert_eq!(merkle_tree.proof_for(LeafIndex(3)), None);
     }
 
     #[test]
-    fn a_merkle_tree_can_add_new_elements() {
-        let transactions = vec![String::from("A")];
-        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
-        let transaction = transactions[0].clone();
-        let proof = merkle_tree.get_proof(transaction.clone());
+    fn a_merkle_tree_proves_two_leaves_are_equal() {
+        let merkle_tree = MerkleTree::new(vec!["X", "Y", "X"]).unwrap();
 
-        assert_eq!(proof.len(), 0);
-        assert!(merkle_tree.verify(transaction, proof));
+        let equal = merkle_tree
+            .equality_proof(LeafIndex(0), LeafIndex(2))
+            .unwrap();
+        assert!(merkle_tree.verify_equality(&equal));
+        let different = merkle_tree
+            .equality_proof(LeafIndex(0), LeafIndex(1))
+            .unwrap();
+        assert!(!merkle_tree.verify_equality(&different));
 
-        merkle_tree.add(String::from("B")).unwrap();
-        let transaction = transactions[0].clone();
-        let proof = merkle_tree.get_proof(transaction.clone());
-        assert_eq!(proof.len(), 1);
-        assert!(merkle_tree.verify(transaction, proof));
+        // Claiming the hash of leaf 0 for leaf 1 doesn't fold to the root
+        let mut forged = different;
+        forged.second.1 = forged.first.1;
+        assert!(!merkle_tree.verify_equality(&forged));
+        // Nor does moving a proof to another index
+        let mut moved = equal;
+        moved.second.0 = LeafIndex(1);
+        assert!(!merkle_tree.verify_equality(&moved));
+        assert_eq!(merkle_tree.equality_proof(LeafIndex(0), LeafIndex(3)), None);
     }
 
     #[test]
-    fn a_merkle_tree_cant_verify_a_transaction_if_not_present() {
-        let transactions = vec![String::from("A"), String::from("B")];
-        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
-        let transaction = String::from("C");
-        let proof = merkle_tree.get_proof(transaction.clone());
+    fn a_merkle_tree_can_hash_its_leaves_several_times() {
+        let transactions = vec!["A", "B", "C"];
+        let default = MerkleTree::new(transactions.clone()).unwrap();
+        let once =
+            MerkleTree::with_config(transactions.clone(), MerkleConfig::default().iterations(1))
+                .unwrap();
+        assert_eq!(once.leaf_hashes(), default.leaf_hashes());
+        assert_eq!(once.root(), default.root());
 
-        assert!(!merkle_tree.verify(transaction, proof));
+        let config = MerkleConfig::default().iterations(1000);
+        let mut stretched = MerkleTree::with_config(transactions.clone(), config).unwrap();
+        let again = MerkleTree::with_config(transactions, config).unwrap();
+        assert_ne!(stretched.leaf_hashes(), default.leaf_hashes());
+        assert_eq!(stretched.leaf_hashes(), again.leaf_hashes());
+
+        let proof = stretched.proof_for(LeafIndex(1)).unwrap();
+        assert!(stretched.verify("B", proof.clone()));
+        let detached =
+            Proof::from_bytes(&stretched.detached_proof(LeafIndex(1)).unwrap().to_bytes()).unwrap();
+        assert_eq!(stretched.verify_versioned(&"B", &detached), Ok(true));
+        assert_eq!(
+            MerkleTree::verify_detached(&"B", &detached, stretched.root()),
+            Ok(false)
+        );
+        assert!(stretched.verify_bytes(&"B".encode(), &proof));
+        stretched.add("D").unwrap();
+        assert_eq!(
+            stretched.root(),
+            MerkleTree::with_config(vec!["A", "B", "C", "D"], config)
+                .unwrap()
+                .root()
+        );
     }
 
     #[test]
-    fn a_merkle_tree_can_have_generic_transactions() {
-        let transactions = vec![1000, 1500, 2000, 3000, 4000, 5500, 7000, 8700];
-        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
-        let transaction = transactions[0].clone();
-        let proof = merkle_tree.get_proof(transaction.clone());
+    fn a_merkle_tree_streams_the_proofs_of_a_range_of_leaves() {
+        let merkle_tree = MerkleTree::new((0..9).collect()).unwrap();
 
-        assert!(merkle_tree.verify(transaction, proof));
+        let proofs: Vec<_> = merkle_tree.proofs_in_range(2, 6).collect();
+        let expected: Vec<_> = (2..6)
+            .map(|index| {
+                (
+                    LeafIndex(index),
+                    merkle_tree.proof_for(LeafIndex(index)).unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(proofs, expected);
+        assert_eq!(merkle_tree.proofs_in_range(7, 20).count(), 2);
+        assert_eq!(merkle_tree.proofs_in_range(6, 2).count(), 0);
+    }
 
-        let transactions = vec![
-            "De aquel amor",
-            "De musica ligera",
-            "Nada nos libra,",
-            "Nada mas queda",
-        ];
-        let mut merkle_tree = MerkleTree::new(transactions.clone()).unwrap();
-        let transaction = transactions[0];
-        let proof = merkle_tree.get_proof(transaction);
+    #[test]
+    fn a_strict_merkle_tree_flags_a_proof_with_a_zeroed_sibling() {
+        let config = MerkleConfig::default().reject_zero_siblings(true);
+        let merkle_tree = MerkleTree::with_config(vec!["A", "B", "C", "D"], config).unwrap();
+        let proof = merkle_tree.detached_proof(LeafIndex(2)).unwrap();
+        assert_eq!(merkle_tree.verify_versioned(&"C", &proof), Ok(true));
 
-        assert!(merkle_tree.verify(transaction, proof));
+        // The last 8 bytes are the hash of the last sibling
+        let mut bytes = proof.to_bytes();
+        let zeroed = bytes.len() - 8;
+        bytes[zeroed..].fill(0);
+        let proof = Proof::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            merkle_tree.verify_versioned(&"C", &proof),
+            Err(MerkleError::SuspiciousZeroSibling { level: 1 })
+        );
+        assert_eq!(
+            merkle_tree.verify_at(&"C", LeafIndex(2), &proof.siblings),
+            Err(MerkleError::SuspiciousZeroSibling { level: 1 })
+        );
+        let mut merkle_tree = merkle_tree;
+        assert!(!merkle_tree.verify_ref(&"C", &proof.siblings));
+        assert!(!merkle_tree.verify_checked(&"C", &proof.siblings));
+        assert!(!merkle_tree.verify_iter("C", proof.siblings.iter().copied()));
+        assert!(!merkle_tree.verify("C", proof.siblings.clone()));
+        let valid = merkle_tree.proof_for(LeafIndex(2)).unwrap();
+        assert!(merkle_tree.verify_ref(&"C", &valid));
+        assert!(merkle_tree.verify("C", valid));
+
+        // Without strict mode, or when zero is the padding, the proof just doesn't verify
+        let lenient = MerkleTree::new(vec!["A", "B", "C", "D"]).unwrap();
+        assert_eq!(
+            lenient.verify_at(&"C", LeafIndex(2), &proof.siblings),
+            Ok(false)
+        );
+        let zero_padded = MerkleTree::with_config(
+            vec!["A", "B", "C", "D"],
+            config.odd_strategy(OddStrategy::ZeroPad),
+        )
+        .unwrap();
+        assert_eq!(
+            zero_padded.verify_at(&"C", LeafIndex(2), &proof.siblings),
+            Ok(false)
+        );
+
+        // Two equal sons give a zero node here, so the proof of "C" is valid but for strict mode
+        type XorTree = MerkleTree<&'static str, XorMerkleHasher>;
+        let leaves = vec!["A", "A", "C", "D"];
+        let lenient = XorTree::with_hasher(leaves.clone(), MerkleConfig::default()).unwrap();
+        let strict = XorTree::with_hasher(leaves, config).unwrap();
+        for (merkle_tree, accepted) in [(lenient, true), (strict, false)] {
+            let index = LeafIndex(2);
+            let proof = merkle_tree.proof_for(index).unwrap();
+            assert!(proof.contains(&SiblingHash::Left(Hash::default())));
+            let compact = CompactProof {
+                siblings: proof.clone(),
+                duplicated_levels: 0,
+            };
+            let leaf_hash = merkle_tree.expected_leaf_for(index).unwrap();
+
+            assert_eq!(merkle_tree.verify_ref(&"C", &proof), accepted);
+            assert_eq!(merkle_tree.verify_checked(&"C", &proof), accepted);
+            assert_eq!(merkle_tree.verify_iter("C", proof.clone()), accepted);
+            assert_eq!(merkle_tree.verify_counted("C", &proof).0, accepted);
+            assert_eq!(merkle_tree.verify_bytes(&"C".encode(), &proof), accepted);
+            assert_eq!(merkle_tree.verify_compact(&"C", &compact), accepted);
+            assert_eq!(merkle_tree.verify_hash(leaf_hash, &proof), accepted);
+            assert_eq!(
+                merkle_tree.verify_diagnostic("C", &proof) == ProofDiagnostic::Valid,
+                accepted
+            );
+            let equality_proof = merkle_tree.equality_proof(index, index).unwrap();
+            assert_eq!(merkle_tree.verify_equality(&equality_proof), accepted);
+            let quorum_proof = merkle_tree.quorum_proof(&[index]).unwrap();
+            assert_eq!(merkle_tree.verify_quorum(&quorum_proof, 1), accepted);
+            let verifier = merkle_tree.prepare_verifier(index).unwrap();
+            assert_eq!(verifier.verify(&"C"), accepted);
+            let snapshot = merkle_tree.snapshot().unwrap();
+            assert_eq!(snapshot.verify(&"C", &proof), accepted);
+            assert_eq!(merkle_tree.verify_at(&"C", index, &proof).is_ok(), accepted);
+        }
+    }
+
+    fn arbitrary_proof() -> impl Strategy<Value = Vec<SiblingHash>> {
+        prop::collection::vec(
+            (any::<u64>(), any::<bool>()).prop_map(|(hash, is_left)| {
+                if is_left {
+                    SiblingHash::Left(Hash::from(hash))
+                } else {
+                    SiblingHash::Right(Hash::from(hash))
+                }
+            }),
+            0..80,
+        )
+    }
+
+    // Malformed proofs have to be rejected, never panic, whatever their length or the index they
+    // point to
+    proptest! {
+        #[test]
+        fn verifying_an_arbitrary_proof_never_panics(
+            leaf_count in 1usize..40,
+            transaction in any::<u64>(),
+            index in any::<usize>(),
+            proof in arbitrary_proof(),
+        ) {
+            let transactions: Vec<u64> = (0..leaf_count as u64).collect();
+            let mut merkle_tree = MerkleTree::new(transactions).unwrap();
+            let tree_head = merkle_tree.tree_head_at(0);
+
+            merkle_tree.verify_ref(&transaction, &proof);
+            merkle_tree.verify_checked(&transaction, &proof);
+            merkle_tree.verify_counted(transaction, &proof);
+            merkle_tree.verify_diagnostic(transaction, &proof);
+            let _ = merkle_tree.verify_at(&transaction, LeafIndex(index), &proof);
+            let leaf_index = LeafIndex(index);
+            let _ = MerkleTree::verify_with_size(&transaction, leaf_index, &proof, &tree_head);
+            MerkleTree::verify_trace(transaction, &proof, tree_head.root);
+            let absence = AbsenceProof {
+                lower: Some((transaction, proof.clone())),
+                upper: Some((transaction.wrapping_add(1), proof.clone())),
+            };
+            merkle_tree.verify_absence(&transaction, &absence);
+            let compact = CompactProof {
+                siblings: proof.clone(),
+                duplicated_levels: index as u64,
+            };
+            merkle_tree.verify_compact(&transaction, &compact);
+            merkle_tree.verify(transaction, proof);
+        }
+
+        #[test]
+        fn decoding_arbitrary_proof_bytes_never_panics(
+            bytes in prop::collection::vec(any::<u8>(), 0..200),
+            transaction in any::<u64>(),
+        ) {
+            if let Ok(proof) = Proof::from_bytes(&bytes) {
+                let _ = MerkleTree::verify_detached(&transaction, &proof, Hash::default());
+            }
+        }
     }
 }
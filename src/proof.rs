@@ -0,0 +1,723 @@
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+
+use crate::error::MerkleError;
+use crate::hash::Hash;
+use crate::hasher::{DefaultMerkleHasher, MerkleHasher};
+use crate::merkle_tree::{LeafIndex, MerkleTree, SiblingHash};
+
+const LEFT: u8 = 0;
+const RIGHT: u8 = 1;
+const SALT: u8 = 2;
+const HEADER_LEN: usize = 13;
+const SIBLING_LEN: usize = 9;
+const BUNDLE_HEADER_LEN: usize = 26;
+const OFFSET_LEN: usize = 8;
+
+/// A proof detached from its tree, tagged with the algorithm that produced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    pub algorithm: u8,
+    /// The [`crate::merkle_tree::MerkleTree::version`] of the tree when the proof was issued.
+    pub version: u64,
+    /// The salt mixed into the leaf hashes, see [`crate::merkle_tree::MerkleTree::new_salted`].
+    pub salt: Option<u64>,
+    pub siblings: Vec<SiblingHash>,
+}
+
+impl Proof {
+    // Layout: the algorithm tag, the big endian version, the big endian number of siblings, then
+    // one direction byte and a big endian hash for every sibling. A salt goes first among the
+    // siblings, with its own tag instead of a direction, so unsalted proofs don't pay for it
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let entries = self.siblings.len() + usize::from(self.salt.is_some());
+        let mut bytes = Vec::with_capacity(HEADER_LEN + entries * SIBLING_LEN);
+        bytes.push(self.algorithm);
+        bytes.extend_from_slice(&self.version.to_be_bytes());
+        let sibling_count =
+            u32::try_from(self.siblings.len()).expect("a proof has less than 2^32 siblings");
+        bytes.extend_from_slice(&sibling_count.to_be_bytes());
+        if let Some(salt) = self.salt {
+            bytes.push(SALT);
+            bytes.extend_from_slice(&salt.to_be_bytes());
+        }
+        write_siblings(&mut bytes, &self.siblings);
+        bytes
+    }
+
+    /// Fails with [`MerkleError::TruncatedProof`] when fewer siblings follow than the header
+    /// announces, which tells a transmission cut short apart from a proof that doesn't verify.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(MerkleError::MalformedProof);
+        }
+        let (header, siblings) = bytes.split_at(HEADER_LEN);
+        let algorithm = header[0];
+        let version = read_u64(&header[1..9]);
+        let mut sibling_count = [0; 4];
+        sibling_count.copy_from_slice(&header[9..]);
+        let sibling_count = u32::from_be_bytes(sibling_count) as usize;
+        let truncated = |siblings: &[u8]| MerkleError::TruncatedProof {
+            expected: sibling_count,
+            found: siblings.len() / SIBLING_LEN,
+        };
+
+        let (salt, siblings) = match siblings.split_first() {
+            Some((&SALT, rest)) => {
+                let Some(salt) = rest.get(..8) else {
+                    return Err(truncated(&[]));
+                };
+                let mut salt_bytes = [0; 8];
+                salt_bytes.copy_from_slice(salt);
+                (Some(u64::from_be_bytes(salt_bytes)), &rest[8..])
+            }
+            _ => (None, siblings),
+        };
+        if siblings.len() < sibling_count.saturating_mul(SIBLING_LEN) {
+            return Err(truncated(siblings));
+        }
+        if siblings.len() != sibling_count * SIBLING_LEN {
+            return Err(MerkleError::MalformedProof);
+        }
+
+        Ok(Self {
+            algorithm,
+            version,
+            salt,
+            siblings: read_siblings(siblings)?,
+        })
+    }
+}
+
+// One direction byte and a big endian hash for every sibling
+fn write_siblings(bytes: &mut Vec<u8>, siblings: &[SiblingHash]) {
+    for sibling_hash in siblings {
+        let (direction, hash) = match sibling_hash {
+            SiblingHash::Left(hash) => (LEFT, hash),
+            SiblingHash::Right(hash) => (RIGHT, hash),
+        };
+        bytes.push(direction);
+        bytes.extend_from_slice(&hash.to_be_bytes());
+    }
+}
+
+// The bytes must be whole sibling records
+fn read_siblings(bytes: &[u8]) -> Result<Vec<SiblingHash>, MerkleError> {
+    bytes
+        .chunks_exact(SIBLING_LEN)
+        .map(|sibling| {
+            let hash = Hash::from(read_u64(&sibling[1..]));
+            match sibling[0] {
+                LEFT => Ok(SiblingHash::Left(hash)),
+                RIGHT => Ok(SiblingHash::Right(hash)),
+                _ => Err(MerkleError::MalformedProof),
+            }
+        })
+        .collect()
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut number = [0; 8];
+    number.copy_from_slice(bytes);
+    u64::from_be_bytes(number)
+}
+
+/// The proofs of every leaf of a tree in one buffer, made by
+/// [`crate::merkle_tree::MerkleTree::export_all_proofs`], so they can be handed out as a single
+/// file from which every client extracts only its own, without the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofBundle {
+    pub algorithm: u8,
+    /// The salt mixed into the leaf hashes, see [`crate::merkle_tree::MerkleTree::new_salted`].
+    pub salt: Option<u64>,
+    pub root: Hash,
+    bytes: Vec<u8>,
+    size: usize,
+}
+
+impl ProofBundle {
+    pub(crate) fn new(
+        algorithm: u8,
+        salt: Option<u64>,
+        root: Hash,
+        proofs: &[Vec<SiblingHash>],
+    ) -> Self {
+        let siblings: usize = proofs.iter().map(Vec::len).sum();
+        let mut bytes = Vec::with_capacity(
+            BUNDLE_HEADER_LEN + proofs.len() * OFFSET_LEN + siblings * SIBLING_LEN,
+        );
+        bytes.push(algorithm);
+        bytes.push(if salt.is_some() { SALT } else { 0 });
+        bytes.extend_from_slice(&salt.unwrap_or_default().to_be_bytes());
+        bytes.extend_from_slice(&u64::from(root).to_be_bytes());
+        bytes.extend_from_slice(&(proofs.len() as u64).to_be_bytes());
+        let mut offset = 0;
+        for proof in proofs {
+            bytes.extend_from_slice(&(offset as u64).to_be_bytes());
+            offset += proof.len() * SIBLING_LEN;
+        }
+        for proof in proofs {
+            write_siblings(&mut bytes, proof);
+        }
+        Self {
+            algorithm,
+            salt,
+            root,
+            bytes,
+            size: proofs.len(),
+        }
+    }
+
+    // Layout: the algorithm tag, a salt tag and the big endian salt, zero when unsalted, the big
+    // endian root and number of leaves, then one big endian offset per leaf to where its siblings
+    // start, counted from the end of the offsets, and the siblings of every leaf like in
+    // `Proof::to_bytes`
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Checks the header and the offsets, so extracting a proof afterwards only fails on a
+    /// malformed sibling.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, MerkleError> {
+        if bytes.len() < BUNDLE_HEADER_LEN {
+            return Err(MerkleError::MalformedProof);
+        }
+        let algorithm = bytes[0];
+        let salt = match bytes[1] {
+            0 => None,
+            SALT => Some(read_u64(&bytes[2..10])),
+            _ => return Err(MerkleError::MalformedProof),
+        };
+        let root = Hash::from(read_u64(&bytes[10..18]));
+        let size =
+            usize::try_from(read_u64(&bytes[18..26])).map_err(|_| MerkleError::MalformedProof)?;
+
+        let siblings_start = size
+            .checked_mul(OFFSET_LEN)
+            .and_then(|offsets| offsets.checked_add(BUNDLE_HEADER_LEN))
+            .filter(|&start| start <= bytes.len())
+            .ok_or(MerkleError::MalformedProof)?;
+        let siblings_len = bytes.len() - siblings_start;
+        let mut previous = 0;
+        for offset in bytes[BUNDLE_HEADER_LEN..siblings_start].chunks_exact(OFFSET_LEN) {
+            let offset = read_u64(offset);
+            if offset < previous
+                || offset > siblings_len as u64
+                || !offset.is_multiple_of(SIBLING_LEN as u64)
+            {
+                return Err(MerkleError::MalformedProof);
+            }
+            previous = offset;
+        }
+        if !siblings_len.is_multiple_of(SIBLING_LEN) {
+            return Err(MerkleError::MalformedProof);
+        }
+
+        Ok(Self {
+            algorithm,
+            salt,
+            root,
+            bytes,
+            size,
+        })
+    }
+
+    /// The number of leaves with a proof in the bundle.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// The proof of the leaf at `index`, decoding only its own siblings.
+    pub fn get(&self, index: LeafIndex) -> Option<Vec<SiblingHash>> {
+        if index.0 >= self.size {
+            return None;
+        }
+        let siblings_start = BUNDLE_HEADER_LEN + self.size * OFFSET_LEN;
+        let offset = |index: usize| {
+            let start = BUNDLE_HEADER_LEN + index * OFFSET_LEN;
+            siblings_start + read_u64(&self.bytes[start..start + OFFSET_LEN]) as usize
+        };
+        let end = if index.0 + 1 < self.size {
+            offset(index.0 + 1)
+        } else {
+            self.bytes.len()
+        };
+        read_siblings(&self.bytes[offset(index.0)..end]).ok()
+    }
+}
+
+/// A proof without the siblings that are just a copy of the node being folded, which happens
+/// where a lone node was duplicated to give it a pair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactProof {
+    pub siblings: Vec<SiblingHash>,
+    /// Bit `i` is set when level `i` of the fold hashes the node with itself.
+    pub duplicated_levels: u64,
+}
+
+/// Folds the siblings in order, from the leaf up, and returns the root the proof commits to.
+/// Siblings can be given by reference or by value, as they are produced.
+pub fn fold_proof<S: Borrow<SiblingHash>>(
+    leaf_hash: Hash,
+    proof: impl IntoIterator<Item = S>,
+) -> Hash {
+    fold_proof_with::<DefaultMerkleHasher, S>(leaf_hash, proof)
+}
+
+/// Like [`fold_proof`], for a tree with another [`MerkleHasher`].
+pub fn fold_proof_with<M: MerkleHasher, S: Borrow<SiblingHash>>(
+    leaf_hash: Hash,
+    proof: impl IntoIterator<Item = S>,
+) -> Hash {
+    proof.into_iter().fold(leaf_hash, |current, sibling_hash| {
+        match sibling_hash.borrow() {
+            SiblingHash::Left(left_hash) => M::hash_pair(*left_hash, current),
+            SiblingHash::Right(right_hash) => M::hash_pair(current, *right_hash),
+        }
+    })
+}
+
+/// A proof folded one sibling at a time, for verifiers that get the siblings from a stream, or
+/// pause between them, without holding the whole proof.
+#[derive(Debug)]
+pub struct FoldState<M: MerkleHasher = DefaultMerkleHasher> {
+    /// The hash folded so far, the leaf hash before any sibling.
+    pub current: Hash,
+    /// The number of siblings folded.
+    pub consumed: usize,
+    hasher: PhantomData<fn() -> M>,
+}
+
+// Derives would require the hasher to be Clone and Eq too, though states hold none
+impl<M: MerkleHasher> Clone for FoldState<M> {
+    fn clone(&self) -> Self {
+        Self {
+            current: self.current,
+            consumed: self.consumed,
+            hasher: PhantomData,
+        }
+    }
+}
+
+impl<M: MerkleHasher> PartialEq for FoldState<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.current == other.current && self.consumed == other.consumed
+    }
+}
+
+impl<M: MerkleHasher> Eq for FoldState<M> {}
+
+impl<M: MerkleHasher> FoldState<M> {
+    pub fn new(leaf_hash: Hash) -> Self {
+        Self {
+            current: leaf_hash,
+            consumed: 0,
+            hasher: PhantomData,
+        }
+    }
+
+    pub fn step(&mut self, sibling: SiblingHash) {
+        self.current = fold_proof_with::<M, _>(self.current, [sibling]);
+        self.consumed += 1;
+    }
+
+    pub fn finish(self, root: Hash) -> bool {
+        self.current == root
+    }
+}
+
+pub fn compact_proof(leaf_hash: Hash, proof: &[SiblingHash]) -> CompactProof {
+    compact_proof_with::<DefaultMerkleHasher>(leaf_hash, proof)
+}
+
+pub fn compact_proof_with<M: MerkleHasher>(leaf_hash: Hash, proof: &[SiblingHash]) -> CompactProof {
+    let mut compact = CompactProof {
+        siblings: Vec::new(),
+        duplicated_levels: 0,
+    };
+    let mut current = leaf_hash;
+    for (level, sibling_hash) in proof.iter().enumerate() {
+        match *sibling_hash {
+            SiblingHash::Right(right_hash)
+                if right_hash == current && level < u64::BITS as usize =>
+            {
+                compact.duplicated_levels |= 1 << level;
+            }
+            sibling_hash => compact.siblings.push(sibling_hash),
+        }
+        current = fold_proof_with::<M, _>(current, [sibling_hash]);
+    }
+    compact
+}
+
+pub fn expand_proof(leaf_hash: Hash, compact: &CompactProof) -> Vec<SiblingHash> {
+    expand_proof_with::<DefaultMerkleHasher>(leaf_hash, compact)
+}
+
+pub fn expand_proof_with<M: MerkleHasher>(
+    leaf_hash: Hash,
+    compact: &CompactProof,
+) -> Vec<SiblingHash> {
+    let levels = compact.siblings.len() + compact.duplicated_levels.count_ones() as usize;
+    let mut siblings = compact.siblings.iter();
+    let mut proof = Vec::with_capacity(levels);
+    let mut current = leaf_hash;
+    for level in 0..levels {
+        let duplicated = compact
+            .duplicated_levels
+            .checked_shr(level as u32)
+            .unwrap_or(0)
+            & 1
+            == 1;
+        let sibling_hash = if duplicated {
+            SiblingHash::Right(current)
+        } else {
+            match siblings.next() {
+                Some(sibling_hash) => *sibling_hash,
+                None => break,
+            }
+        };
+        current = fold_proof_with::<M, _>(current, [&sibling_hash]);
+        proof.push(sibling_hash);
+    }
+    proof
+}
+
+pub fn verify_proof(leaf_hash: Hash, proof: &[SiblingHash], root: Hash) -> bool {
+    fold_proof(leaf_hash, proof) == root
+}
+
+/// Verifies a proof of an unsalted tree without any tree, with the algorithm given explicitly as
+/// the [`MerkleHasher`], like `verify_with_hasher::<Sha256MerkleHasher>(leaf, proof, root)`. The
+/// encoded `leaf` is hashed from [`MerkleHasher::leaf_hasher`] like trees do, so any leaf prefix
+/// of the hasher is written, and the siblings are folded with the same hasher.
+pub fn verify_with_hasher<M: MerkleHasher>(leaf: &[u8], proof: &[SiblingHash], root: Hash) -> bool {
+    let mut leaf_hasher = M::leaf_hasher();
+    leaf_hasher.write(leaf);
+    fold_proof_with::<M, _>(leaf_hasher.finish(), proof) == root
+}
+
+/// Tries the proof with every candidate encoding of the leaf, as the bytes a
+/// [`crate::leaf::LeafEncode`] would give, and succeeds if any of them folds to `root`. Meant for
+/// migrations between encodings, where the verifier doesn't know which one the tree was built with.
+pub fn verify_any_encoding(candidates: &[Vec<u8>], proof: &[SiblingHash], root: Hash) -> bool {
+    candidates
+        .iter()
+        .any(|candidate| verify_proof(MerkleTree::leaf_hash(candidate), proof, root))
+}
+
+/// Whether both proofs fold `leaf_hash` to the same root in as many steps, which is what matters
+/// when deduplicating or caching them: unlike `==`, it holds for proofs that differ only in the
+/// side of a duplicated sibling.
+pub fn proofs_equivalent(a: &[SiblingHash], b: &[SiblingHash], leaf_hash: Hash) -> bool {
+    a.len() == b.len() && fold_proof(leaf_hash, a) == fold_proof(leaf_hash, b)
+}
+
+/// The position of the proven leaf, read from the side its siblings are on at every level, or
+/// `None` if the proof is too long to point into any tree.
+pub fn proof_index(proof: &[SiblingHash]) -> Option<usize> {
+    if proof.len() >= usize::BITS as usize {
+        return None;
+    }
+    Some(
+        proof
+            .iter()
+            .enumerate()
+            .filter(|(_, sibling_hash)| matches!(sibling_hash, SiblingHash::Left(_)))
+            .fold(0, |index, (level, _)| index | 1 << level),
+    )
+}
+
+/// Joins the proof of a leaf inside a sub-tree with the proof of that sub-tree's root inside a
+/// tree built over sub-tree roots (see [`crate::merkle_tree::MerkleTree::from_leaf_hashes`]).
+///
+/// Folding goes leaf to root, so the inner siblings come first: folding them yields the sub-tree
+/// root, which is the leaf hash the outer siblings start from. Hence
+/// `fold_proof(leaf_hash, &chain_proofs(inner, outer))` yields the root of the outer tree.
+pub fn chain_proofs(inner: &[SiblingHash], outer: &[SiblingHash]) -> Vec<SiblingHash> {
+    inner.iter().chain(outer).copied().collect()
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use crate::error::MerkleError;
+    use crate::hash::Hash;
+    use crate::leaf::LeafEncode;
+    use crate::merkle_tree::{LeafIndex, MerkleTree, SiblingHash};
+    use crate::proof::{
+        chain_proofs, compact_proof, expand_proof, fold_proof, proofs_equivalent,
+        verify_any_encoding, FoldState, Proof, ProofBundle,
+    };
+
+    #[test]
+    fn a_proof_can_be_serialized_and_deserialized() {
+        let proof = Proof {
+            algorithm: 1,
+            version: 3,
+            salt: None,
+            siblings: vec![
+                SiblingHash::Right(Hash::from(7)),
+                SiblingHash::Left(Hash::from(u64::MAX)),
+            ],
+        };
+        let bytes = proof.to_bytes();
+
+        assert_eq!(bytes.len(), 31);
+        assert_eq!(Proof::from_bytes(&bytes), Ok(proof.clone()));
+
+        let salted = Proof {
+            salt: Some(42),
+            ..proof
+        };
+        let bytes = salted.to_bytes();
+        assert_eq!(bytes.len(), 40);
+        assert_eq!(Proof::from_bytes(&bytes), Ok(salted));
+    }
+
+    #[test]
+    fn a_proof_cant_be_deserialized_from_malformed_bytes() {
+        assert_eq!(Proof::from_bytes(&[]), Err(MerkleError::MalformedProof));
+        assert_eq!(
+            Proof::from_bytes(&[1, 0, 0]),
+            Err(MerkleError::MalformedProof)
+        );
+        assert_eq!(
+            Proof::from_bytes(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Err(MerkleError::MalformedProof)
+        );
+        let mut bad_direction = [0; 22];
+        bad_direction[12] = 1;
+        bad_direction[13] = 3;
+        assert_eq!(
+            Proof::from_bytes(&bad_direction),
+            Err(MerkleError::MalformedProof)
+        );
+    }
+
+    #[test]
+    fn a_truncated_proof_is_reported_instead_of_failing_to_verify() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        let bytes = merkle_tree.detached_proof(LeafIndex(1)).unwrap().to_bytes();
+
+        // Cutting whole siblings still leaves a proof that parses without the length
+        assert_eq!(
+            Proof::from_bytes(&bytes[..bytes.len() - 9]),
+            Err(MerkleError::TruncatedProof {
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            Proof::from_bytes(&bytes[..bytes.len() - 4]),
+            Err(MerkleError::TruncatedProof {
+                expected: 3,
+                found: 2
+            })
+        );
+        let mut trailing = bytes.clone();
+        trailing.extend_from_slice(&bytes[bytes.len() - 9..]);
+        assert_eq!(
+            Proof::from_bytes(&trailing),
+            Err(MerkleError::MalformedProof)
+        );
+    }
+
+    #[test]
+    fn a_leaf_can_be_proven_up_to_the_root_of_a_tree_of_trees() {
+        let sub_trees = [
+            MerkleTree::new(vec!["A", "B"]).unwrap(),
+            MerkleTree::new(vec!["C", "D", "E"]).unwrap(),
+            MerkleTree::new(vec!["F"]).unwrap(),
+        ];
+        let roots = sub_trees.iter().map(MerkleTree::root).collect();
+        let super_tree = MerkleTree::<&str>::from_leaf_hashes(roots).unwrap();
+
+        let inner = sub_trees[1].proof_for(LeafIndex(2)).unwrap();
+        let outer = super_tree.proof_for(LeafIndex(1)).unwrap();
+        let chained = chain_proofs(&inner, &outer);
+
+        assert_eq!(chained.len(), inner.len() + outer.len());
+        assert_eq!(
+            fold_proof(MerkleTree::leaf_hash(&"E"), &chained),
+            super_tree.root()
+        );
+        assert_ne!(
+            fold_proof(MerkleTree::leaf_hash(&"D"), &chained),
+            super_tree.root()
+        );
+    }
+
+    #[test]
+    fn a_proof_with_a_duplicated_sibling_can_be_compacted() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        let leaf_hash = MerkleTree::leaf_hash(&"C");
+        let proof = merkle_tree.proof_for(LeafIndex(2)).unwrap();
+        let compact = compact_proof(leaf_hash, &proof);
+
+        assert_eq!(proof[0], SiblingHash::Right(leaf_hash));
+        assert_eq!(compact.siblings, proof[1..]);
+        assert_eq!(compact.duplicated_levels, 0b01);
+        assert_eq!(expand_proof(leaf_hash, &compact), proof);
+        assert!(merkle_tree.verify_compact(&"C", &compact));
+
+        let proof = merkle_tree.proof_for(LeafIndex(0)).unwrap();
+        let compact = compact_proof(MerkleTree::leaf_hash(&"A"), &proof);
+        assert_eq!(compact.siblings, proof);
+        assert!(merkle_tree.verify_compact(&"A", &compact));
+    }
+
+    #[test]
+    fn a_proof_verifies_if_any_candidate_encoding_is_the_right_one() {
+        let merkle_tree = MerkleTree::new(vec![1u32, 2, 3]).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(1)).unwrap();
+        let little_endian = 2u32.to_le_bytes().to_vec();
+        let wider = 2u64.encode();
+
+        assert!(verify_any_encoding(
+            &[little_endian.clone(), 2u32.encode(), wider.clone()],
+            &proof,
+            merkle_tree.root()
+        ));
+        assert!(!verify_any_encoding(
+            &[little_endian, wider],
+            &proof,
+            merkle_tree.root()
+        ));
+        assert!(!verify_any_encoding(&[], &proof, merkle_tree.root()));
+    }
+
+    #[test]
+    fn proofs_are_equivalent_if_they_fold_a_leaf_to_the_same_root() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C"]).unwrap();
+        let leaf_hash = MerkleTree::leaf_hash(&"C");
+        let proof = merkle_tree.proof_for(LeafIndex(2)).unwrap();
+        assert!(proofs_equivalent(&proof, &proof.clone(), leaf_hash));
+
+        // "C" is a lone node, so it is hashed with itself on either side
+        let mut flipped = proof.clone();
+        flipped[0] = SiblingHash::Left(leaf_hash);
+        assert_ne!(flipped, proof);
+        assert!(proofs_equivalent(&proof, &flipped, leaf_hash));
+
+        let mut tampered = proof.clone();
+        tampered[1] = SiblingHash::Left(Hash::from(7));
+        assert!(!proofs_equivalent(&proof, &tampered, leaf_hash));
+        assert!(!proofs_equivalent(&proof, &proof[..1], leaf_hash));
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn a_proof_can_be_verified_with_only_its_hasher_and_the_root() {
+        use crate::hasher::{CanonicalMerkleHasher, DefaultMerkleHasher, Sha256MerkleHasher};
+        use crate::proof::verify_with_hasher;
+
+        type Sha256Tree = MerkleTree<&'static str, Sha256MerkleHasher>;
+        let merkle_tree = Sha256Tree::with_hasher(vec!["A", "B", "C"], Default::default()).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(2)).unwrap();
+        let root = merkle_tree.root();
+
+        assert!(verify_with_hasher::<Sha256MerkleHasher>(
+            &"C".encode(),
+            &proof,
+            root
+        ));
+        assert!(!verify_with_hasher::<DefaultMerkleHasher>(
+            &"C".encode(),
+            &proof,
+            root
+        ));
+        assert!(!verify_with_hasher::<Sha256MerkleHasher>(
+            &"B".encode(),
+            &proof,
+            root
+        ));
+
+        // The leaf prefix of the canonical hasher is written
+        let canonical = MerkleTree::new_canonical(vec!["A", "B", "C"]).unwrap();
+        let proof = canonical.proof_for(LeafIndex(2)).unwrap();
+        assert!(verify_with_hasher::<CanonicalMerkleHasher>(
+            &"C".encode(),
+            &proof,
+            canonical.root()
+        ));
+    }
+
+    #[test]
+    fn a_proof_can_be_extracted_from_a_bundle_of_every_proof() {
+        let merkle_tree = MerkleTree::with_salt(vec!["A", "B", "C", "D", "E"], 42).unwrap();
+        let bytes = merkle_tree.export_all_proofs();
+
+        let bundle = ProofBundle::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(bundle.len(), 5);
+        assert_eq!(bundle.root, merkle_tree.root());
+        assert_eq!(bundle.salt, Some(42));
+        assert_eq!(bundle.as_bytes(), bytes);
+        let proof = bundle.get(LeafIndex(3)).unwrap();
+        assert_eq!(proof, merkle_tree.proof_for(LeafIndex(3)).unwrap());
+        let detached = Proof {
+            algorithm: bundle.algorithm,
+            version: 0,
+            salt: bundle.salt,
+            siblings: proof,
+        };
+        assert_eq!(
+            MerkleTree::verify_detached(&"D", &detached, bundle.root),
+            Ok(true)
+        );
+        assert_eq!(bundle.get(LeafIndex(5)), None);
+
+        assert_eq!(
+            ProofBundle::from_bytes(bytes[..bytes.len() - 4].to_vec()),
+            Err(MerkleError::MalformedProof)
+        );
+    }
+
+    #[test]
+    fn a_proof_folded_one_sibling_at_a_time_verifies_like_a_whole_one() {
+        let mut merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D", "E"]).unwrap();
+        let proof = merkle_tree.get_proof("C");
+        let root = merkle_tree.root();
+
+        let mut state: FoldState = FoldState::new(MerkleTree::leaf_hash(&"C"));
+        for sibling_hash in proof.iter().copied() {
+            state.step(sibling_hash);
+        }
+        assert_eq!(state.consumed, proof.len());
+        assert!(merkle_tree.verify("C", proof.clone()));
+        assert!(state.finish(root));
+
+        let mut state: FoldState = FoldState::new(MerkleTree::leaf_hash(&"D"));
+        proof
+            .iter()
+            .for_each(|sibling_hash| state.step(*sibling_hash));
+        assert!(!state.finish(root));
+    }
+
+    #[test]
+    fn fold_states_are_equal_when_they_folded_the_same_siblings() {
+        let merkle_tree = MerkleTree::new(vec!["A", "B", "C", "D"]).unwrap();
+        let proof = merkle_tree.proof_for(LeafIndex(2)).unwrap();
+
+        let mut state: FoldState = FoldState::new(MerkleTree::leaf_hash(&"C"));
+        let paused = state.clone();
+        assert_eq!(state, paused);
+        state.step(proof[0]);
+        assert_ne!(state, paused);
+
+        let mut resumed = paused.clone();
+        resumed.step(proof[0]);
+        assert_eq!(resumed, state);
+        // Same hash, but not as far along the proof
+        let restarted = FoldState::new(state.current);
+        assert_ne!(restarted, state);
+    }
+}
@@ -0,0 +1,113 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::hash::Hash;
+
+/// The committed state of a tree, packaged so callers can sign it externally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TreeHead {
+    pub root: Hash,
+    pub size: usize,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl TreeHead {
+    pub const ENCODED_LEN: usize = 24;
+
+    pub fn new(root: Hash, size: usize) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Self::at(root, size, timestamp)
+    }
+
+    pub fn at(root: Hash, size: usize, timestamp: u64) -> Self {
+        Self {
+            root,
+            size,
+            timestamp,
+        }
+    }
+
+    // Canonical encoding to sign: root, size and timestamp as big endian u64s
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0; Self::ENCODED_LEN];
+        bytes[..8].copy_from_slice(&self.root.to_be_bytes());
+        bytes[8..16].copy_from_slice(&(self.size as u64).to_be_bytes());
+        bytes[16..].copy_from_slice(&self.timestamp.to_be_bytes());
+        bytes
+    }
+}
+
+/// What two parties compare to agree their whole datasets match: equal roots only mean equal
+/// leaves, in the same order, when the hashing algorithm and the number of leaves match too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DatasetCommitment {
+    pub root: Hash,
+    pub size: usize,
+    pub algorithm: u8,
+}
+
+pub fn commitments_match(a: &DatasetCommitment, b: &DatasetCommitment) -> bool {
+    a == b
+}
+
+/// Checks an ed25519 `signature` made by `pubkey` over the canonical encoding of a [`TreeHead`],
+/// so light clients can reject roots that weren't published by a trusted signer.
+#[cfg(feature = "ed25519")]
+pub fn verify_signed_root(tree_head_bytes: &[u8], signature: &[u8], pubkey: &[u8]) -> bool {
+    use ed25519_dalek::{Signature, VerifyingKey};
+
+    if tree_head_bytes.len() != TreeHead::ENCODED_LEN {
+        return false;
+    }
+    let Ok(pubkey) = <&[u8; 32]>::try_from(pubkey) else {
+        return false;
+    };
+    let (Ok(verifying_key), Ok(signature)) = (
+        VerifyingKey::from_bytes(pubkey),
+        Signature::from_slice(signature),
+    ) else {
+        return false;
+    };
+
+    verifying_key
+        .verify_strict(tree_head_bytes, &signature)
+        .is_ok()
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use crate::hash::Hash;
+    use crate::tree_head::TreeHead;
+
+    #[test]
+    fn a_tree_head_has_a_big_endian_encoding() {
+        let tree_head = TreeHead::at(Hash::from(0x0102030405060708), 3, 1_700_000_000);
+        let bytes = tree_head.to_bytes();
+
+        assert_eq!(bytes[..8], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(bytes[8..16], [0, 0, 0, 0, 0, 0, 0, 3]);
+        assert_eq!(bytes[16..], 1_700_000_000u64.to_be_bytes());
+        assert_eq!(bytes, tree_head.to_bytes());
+    }
+
+    #[cfg(feature = "ed25519")]
+    #[test]
+    fn a_signed_tree_head_is_verified_and_a_tampered_one_rejected() {
+        use crate::tree_head::verify_signed_root;
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let pubkey = signing_key.verifying_key().to_bytes();
+        let mut bytes = TreeHead::at(Hash::from(42), 4, 1_700_000_000).to_bytes();
+        let signature = signing_key.sign(&bytes).to_bytes();
+
+        assert!(verify_signed_root(&bytes, &signature, &pubkey));
+
+        bytes[7] ^= 1;
+        assert!(!verify_signed_root(&bytes, &signature, &pubkey));
+        assert!(!verify_signed_root(&bytes, &signature[..10], &pubkey));
+    }
+}